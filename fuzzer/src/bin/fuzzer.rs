@@ -1,37 +1,105 @@
+use core::cell::RefCell;
 use env_logger::Env;
-use std::{env, fs, path::PathBuf};
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    env, fmt, fs,
+    marker::PhantomData,
+    ops::Range,
+    path::{Path, PathBuf},
+    process,
+    rc::Rc,
+    str::FromStr,
+    time::Duration,
+};
 
 use libafl::{
-    bolts::{current_nanos, rands::StdRand, tuples::tuple_list, tuples::Named},
+    bolts::{current_nanos, current_time, rands::StdRand, tuples::tuple_list, tuples::Named},
     corpus::IndexesLenTimeMinimizerCorpusScheduler,
-    corpus::{InMemoryCorpus, OnDiskCorpus, QueueCorpusScheduler},
-    events::SimpleEventManager,
-    feedback_and, feedback_or,
-    feedbacks::{CrashFeedback, TimeFeedback},
+    corpus::{Corpus, QueueCorpusScheduler},
+    events::{Event, EventFirer, SimpleEventManager},
+    executors::HasObservers,
+    feedback_and, feedback_and_fast, feedback_or,
+    feedbacks::{FeedbackStatesTuple, TimeFeedback},
     fuzzer::{Fuzzer, StdFuzzer},
-    inputs::BytesInput,
+    inputs::{BytesInput, HasBytesVec, Input},
     mutators::scheduled::{havoc_mutations, StdScheduledMutator},
+    mutators::token_mutations::Tokens,
     observers::TimeObserver,
     stages::mutational::StdMutationalStage,
-    state::StdState,
+    state::{
+        HasCorpus, HasExecutions, HasFeedbackStates, HasMaxSize, HasMetadata, HasSolutions,
+        StdState,
+    },
+    Error,
 };
 
-use log::{debug, info};
+use log::{debug, error, info, warn};
 
 use fuzzer::{
     config::Config,
     elf,
-    executor::forkserver::ForkserverExecutor,
-    feedback::{bitmap::MaxBitmapFeedback, bitmap_state::CoverageFeedbackState},
-    observer::SharedMemObserver,
+    executor::{
+        forkserver::{ForkserverExecutor, ForkserverRunner, HANGS_STAT, RESPAWNS_STAT},
+        triage::CrashTriage,
+    },
+    feedback::{
+        bitmap::MaxBitmapFeedback,
+        bitmap_state::CoverageFeedbackState,
+        context::CrashContextFeedback,
+        crash::CrashSignalFeedback,
+        reproduce::ReproducedCrashFeedback,
+        stack_hash::StackHashFeedback,
+        suppress::{SuppressionFeedback, SUPPRESSED_STAT},
+    },
+    inst_ranges,
+    limits::{Limit, RunLimits},
+    observer::{ExitStatusObserver, SharedMemObserver},
+    outfile::OutFile,
     power::PowerMutationalStage,
+    queue::QueueCorpus,
+    resume::{self, ResumeState},
+    signals,
+    solutions::CrashCorpus,
     stats::PlotMultiStats,
+    suppress::Suppressions,
 };
 
 const COVERAGE_ID: &str = "coverage";
+const CRASH_COVERAGE_ID: &str = "crash_coverage_feedback_state";
 
 const QEMU_BASE: u64 = 0x5500000000;
 
+/// a crash replay logs every block it runs, which is a lot slower than a forkserver execution
+const TRIAGE_TIMEOUT_FACTOR: u32 = 10;
+
+/// how often the stats are refreshed, same as LibAFL's fuzz_loop
+const STATS_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// written to the plot directory when the fuzzer stops
+const SUMMARY_FILE: &str = "summary.json";
+
+/// exit codes, so CI jobs can tell a clean run from one that found crashes
+const EXIT_NOTHING_FOUND: i32 = 0;
+const EXIT_ERROR: i32 = 1;
+const EXIT_CRASHES_FOUND: i32 = 2;
+
+/// why the fuzzing loop ended
+enum StopReason {
+    /// max_time, max_execs or a signal
+    Limit(Limit),
+    Error(Error),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Limit(limit) => write!(f, "{}", limit),
+            StopReason::Error(e) => write!(f, "error in the fuzzing loop: {:?}", e),
+        }
+    }
+}
+
 /***
  * - [V] configuration and cli
  * - [V] automatic discovery of main address (convert sym to address)
@@ -40,13 +108,13 @@ const QEMU_BASE: u64 = 0x5500000000;
  * - [ ] power schedule mutation scheduler
  * - [ ] custom mutator
  * - [ ] implement multi-client main
- * - [ ] make negative objective to hide well known crashes
- * - [ ] timer to stop fuzzing after one minute
+ * - [V] make negative objective to hide well known crashes
+ * - [V] timer to stop fuzzing after one minute
  * - [X] count unique objectives
  * - [ ] don't save crashes that are triggered by the same path
  * - [ ] unique queue and crash file names
  * - [ ] fork afl++ to make permenent qemu patches
- * - [ ] retry crashes to make sure it is not a "mistake"
+ * - [V] retry crashes to make sure it is not a "mistake"
  * - [V] count objectives
  * - [V] make sure we can catch a crash
  * - [V] print out coverage stats
@@ -66,47 +134,326 @@ const QEMU_BASE: u64 = 0x5500000000;
  *                 not be interested in this testcase
  */
 
-fn get_args() -> Result<(String, Vec<String>), String> {
-    let target: String;
-    let args: Vec<String> = env::args().collect();
+const DEFAULT_CONFIG_PATH: &str = "./config.ini";
 
-    if let Some(h) = args.get(1) {
-        target = h.to_string();
-    } else {
-        return Err("Must specify target binary to be fuzzed".to_string());
+const USAGE: &str = "\
+usage: fuzzer [options] [--] <target> [target args...]
+
+options:
+  -c, --config <path>         config file (default ./config.ini)
+  -P, --profile <name>        read [profile.<name>] of the config file over [general]
+  -i, --input <dir>           directory of the initial corpus
+  -o, --output <dir>          keep crashes, hangs, queue, plots and state under <dir>
+  -t, --timeout <ms>          timeout of a single execution
+  -m, --map-size <bytes>      size of the coverage map
+  -p, --persistent-sym <sym>  symbol QEMU loops on in persistent mode
+  -Q, --qemu <path>           path to afl-qemu-trace
+  -s, --seed <n>              seed of the random generator
+      --max-time <secs>       stop fuzzing after this many seconds
+      --max-execs <n>         stop fuzzing after this many executions
+      --stdin                 deliver testcases through the target's stdin
+      --resume                continue from the state saved by a previous run
+  -h, --help                  print this message
+
+options end at the target or at --. @@ in the target args is replaced with the testcase file,
+it is the only arg if none are given and testcases don't go through stdin.
+
+config keys can also be set with FUZZER_<KEY> environment variables (FUZZER_QEMU_PATH for
+qemu_path), they win over the config file but not over options.";
+
+/// command line of the fuzzer. everything but the target is optional, values given here win
+/// over the config file
+#[derive(Default, Debug)]
+struct Args {
+    target: String,
+    args: Vec<String>,
+    config_path: String,
+    profile: Option<String>,
+    corpus_path: Option<PathBuf>,
+    output_dir: Option<PathBuf>,
+    timeout: Option<Duration>,
+    map_size: Option<usize>,
+    persistent_sym: Option<String>,
+    qemu_path: Option<String>,
+    seed: Option<u64>,
+    use_stdin: bool,
+    resume: bool,
+    max_time: Option<Duration>,
+    max_execs: Option<u64>,
+    help: bool,
+}
+
+impl Args {
+    fn apply(&self, config: &mut Config) {
+        if let Some(corpus_path) = &self.corpus_path {
+            config.corpus_path = corpus_path.clone();
+        }
+
+        if let Some(output_dir) = &self.output_dir {
+            config.set_output_dir(output_dir);
+        }
+
+        if let Some(timeout) = self.timeout {
+            config.timeout = timeout;
+        }
+
+        if let Some(map_size) = self.map_size {
+            config.map_size = map_size;
+        }
+
+        if let Some(persistent_sym) = &self.persistent_sym {
+            config.persistent_sym = persistent_sym.clone();
+        }
+
+        if let Some(qemu_path) = &self.qemu_path {
+            config.qemu_path = qemu_path.clone();
+        }
+
+        if self.max_time.is_some() {
+            config.max_time = self.max_time;
+        }
+
+        if self.max_execs.is_some() {
+            config.max_execs = self.max_execs;
+        }
+
+        config.use_stdin |= self.use_stdin;
+        config.resume |= self.resume;
     }
+}
 
-    let mut leftover_args = (&args[2..]).to_vec();
-    if leftover_args.len() == 0 {
-        leftover_args.push("@@".to_string());
+/// value of a flag like `--max-time 60`
+fn flag_value<T: FromStr>(
+    argv: &mut impl Iterator<Item = String>,
+    flag: &str,
+) -> Result<T, String> {
+    let value = argv
+        .next()
+        .ok_or_else(|| format!("{} needs a value", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {}: {}", flag, value))
+}
+
+/// a flag given more than once keeps its last value
+fn get_args(mut argv: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut cli = Args {
+        config_path: DEFAULT_CONFIG_PATH.to_string(),
+        ..Default::default()
+    };
+
+    let mut positional = Vec::new();
+    while let Some(arg) = argv.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                cli.help = true;
+                return Ok(cli);
+            }
+            "-c" | "--config" => cli.config_path = flag_value(&mut argv, &arg)?,
+            "-P" | "--profile" => cli.profile = Some(flag_value(&mut argv, &arg)?),
+            "-i" | "--input" => cli.corpus_path = Some(flag_value(&mut argv, &arg)?),
+            "-o" | "--output" => cli.output_dir = Some(flag_value(&mut argv, &arg)?),
+            "-t" | "--timeout" => {
+                cli.timeout = Some(Duration::from_millis(flag_value(&mut argv, &arg)?))
+            }
+            "-m" | "--map-size" => cli.map_size = Some(flag_value(&mut argv, &arg)?),
+            "-p" | "--persistent-sym" => cli.persistent_sym = Some(flag_value(&mut argv, &arg)?),
+            "-Q" | "--qemu" => cli.qemu_path = Some(flag_value(&mut argv, &arg)?),
+            "-s" | "--seed" => cli.seed = Some(flag_value(&mut argv, &arg)?),
+            "--max-time" => cli.max_time = Some(Duration::from_secs(flag_value(&mut argv, &arg)?)),
+            "--max-execs" => cli.max_execs = Some(flag_value(&mut argv, &arg)?),
+            "--stdin" => cli.use_stdin = true,
+            "--resume" => cli.resume = true,
+            "--" => {
+                positional.extend(argv.by_ref());
+            }
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {}", arg)),
+            // the target, everything after it belongs to the target
+            _ => {
+                positional.push(arg);
+                positional.extend(argv.by_ref());
+            }
+        }
     }
 
-    debug!("args {} {:?}", &target, &leftover_args);
+    let mut positional = positional.into_iter();
+    cli.target = positional
+        .next()
+        .ok_or_else(|| "Must specify target binary to be fuzzed".to_string())?;
+    cli.args = positional.collect();
 
-    return Ok((target, leftover_args.clone()));
+    debug!("args {:?}", cli);
+    Ok(cli)
 }
 
 pub fn create_dirs(config: &Config) {
     if let Some(plot) = &config.plot_path {
-        // TODO don't fail if directory exists
-        fs::remove_dir_all(plot).expect("Error deleting pervious plots");
+        if Path::new(plot).exists() {
+            fs::remove_dir_all(plot).expect("Error deleting pervious plots");
+        }
 
         fs::create_dir_all(plot).expect("Error while creating plot directory");
     }
+
+    if let Some(queue) = &config.queue_path {
+        // entries of an older run are kept, `QueueCorpus` numbers new ones after them
+        fs::create_dir_all(queue).expect("Error while creating queue directory");
+    }
+
+    if let Some(hangs) = &config.hangs_path {
+        fs::create_dir_all(hangs).expect("Error while creating hangs directory");
+    }
+
+    if let Some(state) = &config.state_path {
+        fs::create_dir_all(state).expect("Error while creating state directory");
+    }
+}
+
+/// pc: and sym: offsets only get the load base added if the target is PIE
+fn load_suppressions(path: &Path, target: &str) -> Result<Suppressions, Error> {
+    let load_base = elf::load_base(target, QEMU_BASE)
+        .map_err(|e| Error::IllegalArgument(format!("Can't read {}: {}", target, e)))?;
+    Suppressions::load(path, target, load_base)
+}
+
+/// guest address ranges of the functions, sections and ranges to instrument and to exclude
+fn resolve_inst_targets(
+    config: &Config,
+    target: &str,
+) -> Result<(Vec<Range<u64>>, Vec<Range<u64>>), Error> {
+    let inst = inst_ranges::resolve(target, &config.inst_targets, QEMU_BASE)?;
+    let exclude = inst_ranges::resolve(target, &config.exclude_targets, QEMU_BASE)?;
+    Ok((inst, exclude))
+}
+
+/// directory the queue is saved to, so a resumed run can dry-run it again. `queue_path` is
+/// only written for AFL++ tools and may be missing or shared with other runs
+fn get_queue_dir(config: &Config) -> Option<PathBuf> {
+    config.state_path.as_ref().map(|state| state.join("queue"))
 }
 
 fn get_stats(config: &Config) -> PlotMultiStats {
     if let Some(plot_path) = &config.plot_path {
-        PlotMultiStats::new_with_plot(PathBuf::from(plot_path), vec![COVERAGE_ID.to_string()])
+        PlotMultiStats::new_with_plot(
+            PathBuf::from(plot_path),
+            vec![
+                COVERAGE_ID.to_string(),
+                HANGS_STAT.to_string(),
+                RESPAWNS_STAT.to_string(),
+                SUPPRESSED_STAT.to_string(),
+            ],
+        )
     } else {
         PlotMultiStats::new()
     }
 }
 
+/// save the queue and everything needed to resume from it, if the config asks for it
+fn save_state<C, FT, I, S>(
+    state_path: Option<&PathBuf>,
+    queue_dir: Option<&PathBuf>,
+    state: &S,
+    paths: &HashMap<u64, usize>,
+    stack_hashes: &HashSet<u64>,
+) -> Result<(), Error>
+where
+    C: Corpus<I>,
+    FT: FeedbackStatesTuple,
+    I: Input + HasBytesVec,
+    S: HasCorpus<C, I> + HasFeedbackStates<FT>,
+{
+    let state_path = match state_path {
+        Some(state_path) => state_path,
+        None => return Ok(()),
+    };
+
+    if let Some(queue_dir) = queue_dir {
+        resume::save_queue(state, queue_dir)?;
+    }
+
+    ResumeState::collect(
+        state,
+        &[COVERAGE_ID, CRASH_COVERAGE_ID],
+        paths.clone(),
+        stack_hashes.clone(),
+    )
+    .save(state_path)?;
+
+    info!("[+] saved fuzzer state to {:?}", state_path);
+    Ok(())
+}
+
+/// what the fuzzer did, printed and written to the plot directory when it stops
+#[derive(Serialize, Debug)]
+struct Summary {
+    stop_reason: String,
+    run_time_secs: u64,
+    executions: usize,
+    coverage: u64,
+    queue: usize,
+    crashes: usize,
+    hangs: u64,
+}
+
+impl Summary {
+    fn log(&self) {
+        info!("[+] fuzzing stopped: {}", self.stop_reason);
+        info!(
+            "[+] run time {}s, {} executions, coverage {}, queue {}, crashes {}, hangs {}",
+            self.run_time_secs,
+            self.executions,
+            self.coverage,
+            self.queue,
+            self.crashes,
+            self.hangs
+        );
+    }
+
+    fn write(&self, dir: &Path) -> Result<(), Error> {
+        let data = serde_json::to_vec_pretty(self)
+            .map_err(|e| Error::Serialize(format!("Error serializing summary: {}", e)))?;
+        fs::write(dir.join(SUMMARY_FILE), data)?;
+        Ok(())
+    }
+}
+
 pub fn main() {
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
-    let (target, args) = get_args().expect("Error while parsing arguments");
-    let config = Config::parse("./config.ini");
+    process::exit(run(env::args().skip(1)));
+}
+
+/// fuzz until a limit is reached or something breaks, returns the exit code. `argv` is the
+/// command line without the program name
+fn run(argv: impl Iterator<Item = String>) -> i32 {
+    let cli = match get_args(argv) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return EXIT_ERROR;
+        }
+    };
+
+    if cli.help {
+        println!("{}", USAGE);
+        return EXIT_NOTHING_FOUND;
+    }
+
+    let parsed = Config::parse_with(&cli.config_path, cli.profile.as_deref(), |config| {
+        cli.apply(config)
+    });
+    let config = match parsed {
+        Ok(config) => config,
+        Err(e) => {
+            error!("[!] {}", e);
+            return EXIT_ERROR;
+        }
+    };
+
+    let (target, mut args) = (cli.target, cli.args);
+    let use_stdin = config.use_stdin;
+    if args.is_empty() && !use_stdin {
+        args.push("@@".to_string());
+    }
 
     // find the persistent address to be looped in QEMU-AFL persistent mode
     let persistent_addr = Some(format!(
@@ -114,10 +461,17 @@ pub fn main() {
         elf::find_addr_by_sym(&target, &config.persistent_sym).unwrap() + QEMU_BASE
     ));
 
+    // before QEMU is started, from here on a signal only asks the fuzz loop to stop and we get
+    // to kill QEMU and remove our files on the way out
+    signals::install_stop_handlers().expect("Error installing signal handlers");
+
     create_dirs(&config);
+    let queue_dir = get_queue_dir(&config);
     debug!("config = {:?}", config);
 
-    let rand = StdRand::with_seed(current_nanos());
+    let seed = cli.seed.unwrap_or_else(current_nanos);
+    info!("[+] random seed {}", seed);
+    let rand = StdRand::with_seed(seed);
     let stats = get_stats(&config);
     let mut mgr = SimpleEventManager::<BytesInput, PlotMultiStats>::new(stats);
 
@@ -125,29 +479,152 @@ pub fn main() {
     // each target run
     let coverage_observer: SharedMemObserver<u8> =
         SharedMemObserver::new(COVERAGE_ID, "__AFL_SHM_ID", config.map_size);
+    // tell QEMU how big the map is, just like the shared memory id it is inherited through env
+    env::set_var("AFL_MAP_SIZE", config.map_size.to_string());
     let time_observer = TimeObserver::new("Time Observer");
+    // filled in by the executor with the decoded status of every run
+    let exit_status_observer = ExitStatusObserver::new();
+
+    // number of coverage ids depends on how we interpret the map
+    let state_size = config.coverage_mode.state_size(config.map_size);
+
+    // the same environment for every QEMU we start
+    let mut qemu_env = config.qemu_env();
+    match resolve_inst_targets(&config, &target) {
+        Ok((inst, exclude)) => {
+            qemu_env.add_inst_ranges(&inst);
+            qemu_env.add_exclude_ranges(&exclude);
+        }
+        Err(e) => {
+            error!("[!] {}", e);
+            return EXIT_ERROR;
+        }
+    }
+
+    // replays crashes to find where they happened, needed for triage and for suppressions
+    let triage = if config.triage || config.suppression_path.is_some() {
+        let mut triage = CrashTriage::new(
+            config.qemu_path.clone(),
+            qemu_env.clone(),
+            target.clone(),
+            args.clone(),
+            use_stdin,
+            config.input_dir.as_deref(),
+            config.max_input_len as u64,
+        );
+        triage.set_depth(config.triage_depth);
+        triage.set_timeout(config.timeout * TRIAGE_TIMEOUT_FACTOR);
+        match elf::load_base(&target, QEMU_BASE) {
+            Ok(load_base) => triage.set_load_base(load_base),
+            Err(e) => {
+                error!("[!] Can't read {}: {}", target, e);
+                return EXIT_ERROR;
+            }
+        }
+        Some(Rc::new(RefCell::new(triage)))
+    } else {
+        None
+    };
+
+    let suppressions = match &config.suppression_path {
+        Some(path) => match load_suppressions(path, &target) {
+            Ok(suppressions) => suppressions,
+            Err(e) => {
+                error!("[!] bad suppression file: {}", e);
+                return EXIT_ERROR;
+            }
+        },
+        None => Suppressions::default(),
+    };
+    let suppressions = Rc::new(suppressions);
 
     // feedback-state holds all-time coverage while feedback holds the last executions coverage
     // feedback will query State and ask it for it's feedback-state by name
-    let feedback_state = CoverageFeedbackState::new(COVERAGE_ID, config.map_size * 8);
-    let feedback = feedback_or!(
-        MaxBitmapFeedback::new(COVERAGE_ID),
-        TimeFeedback::new_with_observer(&time_observer)
+    let feedback_state = CoverageFeedbackState::new(COVERAGE_ID, state_size);
+    let mut bitmap_feedback = MaxBitmapFeedback::new(COVERAGE_ID);
+    bitmap_feedback.set_mode(config.coverage_mode);
+    let feedback = feedback_and_fast!(
+        feedback_or!(
+            bitmap_feedback,
+            TimeFeedback::new_with_observer(&time_observer)
+        ),
+        // known crashes don't get into the queue either, if asked to
+        SuppressionFeedback::new(
+            if config.suppress_queue {
+                triage.clone()
+            } else {
+                None
+            },
+            suppressions.clone(),
+            false,
+        )
     );
 
     // create another feedback-state so we don't save two crashes with the same coverage
     // but on the other hand don't discard a crash if the path was seen but didn't crash yet
-    let crash_coverage_state =
-        CoverageFeedbackState::new("crash_coverage_feedback_state", config.map_size * 8);
-    let objective = feedback_and!(
-        // Must be a crash
-        CrashFeedback::new(),
-        // Take it onlt if trigger new coverage over crashes
-        MaxBitmapFeedback::new_with_names(
-            COVERAGE_ID,
-            "crash_coverage_feedback_state",
-            "crash_bitmap_feedback"
-        )
+    let crash_coverage_state = CoverageFeedbackState::new(CRASH_COVERAGE_ID, state_size);
+    let mut crash_bitmap_feedback =
+        MaxBitmapFeedback::new_with_names(COVERAGE_ID, CRASH_COVERAGE_ID, "crash_bitmap_feedback");
+    crash_bitmap_feedback.set_mode(config.coverage_mode);
+
+    // replaying is slow, so it only happens for crashes that passed the coverage check
+    let stack_hash_feedback = StackHashFeedback::new(
+        if config.triage { triage.clone() } else { None },
+        config.crash_path.clone(),
+    );
+    // saved with the rest of the state so a resumed run doesn't save the same crashes again
+    let seen_hashes = stack_hash_feedback.seen_hashes();
+
+    // how to run a crash by hand, written into every crash report
+    let mut reproduce_command: Vec<String> = qemu_env
+        .vars()
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect();
+    reproduce_command.push(config.qemu_path.clone());
+    reproduce_command.push(target.clone());
+    reproduce_command.extend(args.iter().cloned());
+
+    // QEMU is started before the fuzzer exists, the objective needs the runner to re-run crashes
+    let out_file = OutFile::new_unique(config.input_dir.as_deref(), config.max_input_len as u64);
+    let runner = ForkserverRunner::new(
+        &config.qemu_path,
+        qemu_env,
+        persistent_addr,
+        config.persistent_hook,
+        &target,
+        args,
+        out_file,
+        use_stdin,
+        config.shmem_input,
+        config.timeout,
+    )
+    .expect("Failed to start the forkserver");
+    let runner = Rc::new(RefCell::new(runner));
+
+    let objective = ReproducedCrashFeedback::new(
+        feedback_and!(
+            // Must be a crash, remember which signal killed the target
+            CrashSignalFeedback::new(),
+            feedback_and!(
+                // remember where it came from and how long it ran
+                CrashContextFeedback::new_with_observer(&time_observer),
+                feedback_and_fast!(
+                    // Skip known crashes, checked first so every one of them is counted
+                    SuppressionFeedback::new(triage, suppressions, true),
+                    feedback_and_fast!(
+                        // Take it onlt if trigger new coverage over crashes
+                        crash_bitmap_feedback,
+                        // and, with triage, only if it crashed somewhere new
+                        stack_hash_feedback
+                    )
+                )
+            )
+        ),
+        // make sure it is not a "mistake" before saving it
+        COVERAGE_ID,
+        runner.clone(),
+        config.crash_repro_runs,
     );
 
     let scheduler = IndexesLenTimeMinimizerCorpusScheduler::new(QueueCorpusScheduler::new());
@@ -156,45 +633,260 @@ pub fn main() {
         PowerMutationalStage::new(StdScheduledMutator::new(havoc_mutations())),
     );
 
-    // TODO respect config
-    let temp_corpus = InMemoryCorpus::new();
-    let solution_corpus =
-        OnDiskCorpus::new(config.crash_path).expect("Invalid crash directory path");
+    // interesting testcases, also written to queue_path if it is set
+    let queue_corpus = QueueCorpus::new(config.queue_path.clone());
+    // crashes, each with a json report next to it
+    let solution_corpus = CrashCorpus::new(config.crash_path, reproduce_command, use_stdin)
+        .expect("Invalid crash directory path");
 
     let mut state = StdState::new(
         rand,
-        temp_corpus,
+        queue_corpus,
         solution_corpus,
         tuple_list!(feedback_state, crash_coverage_state),
     );
 
+    // don't let mutations grow testcases beyond what the target can get
+    state.set_max_size(config.max_input_len);
+
     let mut fuzzer = StdFuzzer::new(scheduler, feedback, objective);
-    let mut executor = ForkserverExecutor::new(
-        &config.qemu_path,
-        &config.ld_library_path.unwrap_or("".to_string()),
-        persistent_addr,
-        &target,
-        args,
-        tuple_list!(coverage_observer, time_observer),
-        &mut fuzzer,
-        &mut state,
-        &mut mgr,
-    )
-    .expect("Failed to create the Executor".into());
+    let mut executor = ForkserverExecutor::with_runner(
+        runner,
+        tuple_list!(coverage_observer, time_observer, exit_status_observer),
+    );
+
+    executor.set_oversize_policy(config.oversize_policy);
+
+    if let Some(hangs_path) = config.hangs_path {
+        executor.set_hangs_path(hangs_path);
+    }
+
+    // agree with the target on the map size before the dry run records any coverage
+    let reported_map_size = executor.forkserver().map_size();
+    if let Some(map_size) = reported_map_size {
+        let coverage_observer = executor
+            .observers_mut()
+            .match_name_mut::<SharedMemObserver<u8>>(COVERAGE_ID)
+            .unwrap();
+
+        if map_size > coverage_observer.capacity() {
+            // the target writes beyond our map, give it a bigger one and restart QEMU
+            info!("[+] growing coverage map to {} bytes", map_size);
+            coverage_observer.grow(map_size);
+            env::set_var("AFL_MAP_SIZE", map_size.to_string());
+            executor
+                .mut_forkserver()
+                .reload()
+                .expect("Error restarting QEMU with a bigger coverage map");
+        }
+
+        // only look at the part of the map the target actually writes to
+        executor
+            .observers_mut()
+            .match_name_mut::<SharedMemObserver<u8>>(COVERAGE_ID)
+            .unwrap()
+            .set_map_size(map_size)
+            .expect("Error resizing the coverage map");
+
+        let state_size = config.coverage_mode.state_size(map_size);
+        for name in [COVERAGE_ID, CRASH_COVERAGE_ID].iter() {
+            state
+                .feedback_states_mut()
+                .match_name_mut::<CoverageFeedbackState>(name)
+                .unwrap()
+                .resize(state_size);
+        }
+    }
+
+    // tokens the target sent in the forkserver handshake are used by the token mutations
+    if let Some(autodict) = executor.forkserver().autodict() {
+        state.add_metadata(Tokens::new(autodict.clone()));
+    }
+
+    let saved_state = match &config.state_path {
+        // the config check makes sure there is a state_path with --resume
+        Some(state_path) if config.resume => {
+            ResumeState::load(state_path).expect("Error loading the saved fuzzer state")
+        }
+        _ => None,
+    };
+
+    // before the dry run, crashes in the saved queue were saved by the previous run
+    if let Some(saved_state) = &saved_state {
+        seen_hashes
+            .borrow_mut()
+            .extend(saved_state.stack_hashes.iter().cloned());
+    }
 
-    let corpuses = vec![config.corpus_path];
+    let mut corpuses = vec![config.corpus_path];
+    if saved_state.is_some() {
+        // the saved queue goes first so its testcases are the ones that claim the coverage
+        if let Some(queue_dir) = &queue_dir {
+            corpuses.insert(0, queue_dir.clone());
+        }
+    } else if config.resume {
+        info!("[!] nothing to resume from, starting a new campaign");
+    }
 
     // this should dry-run all files in corpus folder and record coverage+time for them
-    state
-        .load_initial_inputs(&mut fuzzer, &mut executor, &mut mgr, corpuses.as_slice())
-        .expect(&format!(
-            "Failed to load initial corpus from {:?}",
-            corpuses
-        ));
-
-    info!("[+] done loading initial corpus");
-
-    fuzzer
-        .fuzz_loop(&mut stages, &mut executor, &mut state, &mut mgr)
-        .expect("Error in the fuzzing loop".into());
+    match state.load_initial_inputs(&mut fuzzer, &mut executor, &mut mgr, corpuses.as_slice()) {
+        Ok(_) => info!("[+] done loading initial corpus"),
+        // the fuzz loop below stops right away and we still clean up
+        Err(Error::ShuttingDown) => info!("[!] stopped while loading the initial corpus"),
+        Err(e) => panic!("Failed to load initial corpus from {:?}: {:?}", corpuses, e),
+    }
+
+    // the dry run rebuilt the queue, now bring back what the previous run knew beyond it
+    if let Some(saved_state) = saved_state {
+        let paths = saved_state.restore(&mut state);
+        stages.0.set_paths(paths);
+        info!("[+] resumed fuzzer state from {:?}", config.state_path);
+    }
+
+    let start_time = current_time();
+    let mut last_stats = current_time();
+    let mut last_save = current_time();
+
+    // max_time counts from the end of the dry run, signals were honoured all along
+    executor.set_limits(RunLimits::new(config.max_time, config.max_execs));
+
+    let stop_reason = loop {
+        match fuzzer.fuzz_one(&mut stages, &mut executor, &mut state, &mut mgr) {
+            Ok(_) => {}
+            // the executor refuses to run anything once a limit is reached
+            Err(Error::ShuttingDown) => match executor.limit_reached() {
+                Some(limit) => break StopReason::Limit(limit),
+                None => break StopReason::Error(Error::ShuttingDown),
+            },
+            Err(e) => break StopReason::Error(e),
+        }
+
+        let now = current_time();
+
+        if now - last_stats > STATS_TIMEOUT {
+            let executions = *state.executions();
+            mgr.fire(
+                &mut state,
+                Event::UpdateStats {
+                    executions,
+                    time: now,
+                    phantom: PhantomData,
+                },
+            )
+            .expect("Error reporting stats");
+            last_stats = now;
+        }
+
+        if now - last_save > config.save_interval {
+            // a full disk shouldn't end the campaign, the next save may work again
+            if let Err(e) = save_state(
+                config.state_path.as_ref(),
+                queue_dir.as_ref(),
+                &state,
+                stages.0.paths(),
+                &seen_hashes.borrow(),
+            ) {
+                warn!("[!] Error saving the fuzzer state: {}", e);
+            }
+            last_save = now;
+        }
+    };
+
+    // don't lose what we found since the last save
+    if let Err(e) = save_state(
+        config.state_path.as_ref(),
+        queue_dir.as_ref(),
+        &state,
+        stages.0.paths(),
+        &seen_hashes.borrow(),
+    ) {
+        error!("[!] Error saving the fuzzer state: {}", e);
+    }
+
+    let summary = Summary {
+        stop_reason: stop_reason.to_string(),
+        run_time_secs: (current_time() - start_time).as_secs(),
+        executions: *state.executions(),
+        coverage: state
+            .feedback_states()
+            .match_name::<CoverageFeedbackState>(COVERAGE_ID)
+            .map_or(0, |s| s.get_all_time_count()),
+        queue: state.corpus().count(),
+        crashes: state.solutions().count(),
+        hangs: executor.hangs(),
+    };
+    summary.log();
+
+    if let Some(plot_path) = &config.plot_path {
+        if let Err(e) = summary.write(Path::new(plot_path)) {
+            warn!("[!] failed writing the summary: {}", e);
+        }
+    }
+
+    // QEMU is killed and the plot file flushed when everything is dropped on return
+    match stop_reason {
+        StopReason::Error(_) => EXIT_ERROR,
+        _ if summary.crashes > 0 => EXIT_CRASHES_FOUND,
+        _ => EXIT_NOTHING_FOUND,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn parses_flags_and_target() {
+        let cli = get_args(argv(&["-t", "50", "--stdin", "./target", "-x", "@@"])).unwrap();
+        assert_eq!(cli.timeout, Some(Duration::from_millis(50)));
+        assert!(cli.use_stdin);
+        assert_eq!(cli.config_path, DEFAULT_CONFIG_PATH);
+        assert_eq!(cli.target, "./target");
+        // flags after the target belong to the target
+        assert_eq!(cli.args, vec!["-x", "@@"]);
+    }
+
+    #[test]
+    fn rejects_unknown_flags() {
+        let err = get_args(argv(&["--bogus", "./target"])).unwrap_err();
+        assert!(err.contains("--bogus"));
+    }
+
+    #[test]
+    fn rejects_missing_and_bad_values() {
+        assert_eq!(
+            get_args(argv(&["--max-time"])).unwrap_err(),
+            "--max-time needs a value"
+        );
+        assert!(get_args(argv(&["--max-time", "soon", "./target"]))
+            .unwrap_err()
+            .contains("soon"));
+    }
+
+    #[test]
+    fn last_repeated_flag_wins() {
+        let cli = get_args(argv(&["-t", "10", "--timeout", "20", "./target"])).unwrap();
+        assert_eq!(cli.timeout, Some(Duration::from_millis(20)));
+    }
+
+    #[test]
+    fn help_needs_no_target() {
+        let cli = get_args(argv(&["-c", "other.ini", "--help", "--bogus"])).unwrap();
+        assert!(cli.help);
+        assert_eq!(cli.config_path, "other.ini");
+    }
+
+    #[test]
+    fn bad_arguments_fail_the_run() {
+        assert_eq!(run(argv(&["--bogus", "./target"])), EXIT_ERROR);
+        assert_eq!(run(argv(&["--stdin"])), EXIT_ERROR);
+        assert_eq!(run(argv(&["--help"])), EXIT_NOTHING_FOUND);
+    }
 }
@@ -1,12 +1,271 @@
 use configparser::ini::Ini;
-use std::path::PathBuf;
+use log::debug;
+use std::env;
+use std::fmt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::{
+    coverage::CoverageMode, executor::qemu_env::QemuEnv, inst_ranges::RangeSpec,
+    outfile::OversizePolicy,
+};
 
 const DEFAULT_MAP_SIZE: u64 = 1 << 10;
+const DEFAULT_TIMEOUT_MS: u64 = 1000;
+const DEFAULT_MAX_INPUT_LEN: u64 = 1 << 20;
+const DEFAULT_SAVE_INTERVAL_SECS: u64 = 300;
+const DEFAULT_TRIAGE_DEPTH: u64 = 5;
+const DEFAULT_CRASH_REPRO_RUNS: u64 = 0;
+
+const GENERAL_SECTION: &str = "general";
+/// `[profile.ci]` is selected with `--profile ci` and inherits everything it doesn't set from
+/// `[general]`
+const PROFILE_PREFIX: &str = "profile.";
+/// extra environment of QEMU, one variable per key
+const QEMU_ENV_SECTION: &str = "qemu_env";
+/// extra environment of the target, passed through QEMU_SET_ENV
+const GUEST_ENV_SECTION: &str = "guest_env";
+/// prefix of the environment variables that override config keys
+const ENV_PREFIX: &str = "FUZZER_";
+
+/// every key of the general section, anything else is most likely a typo
+const KNOWN_KEYS: &[&str] = &[
+    "map_size",
+    "coverage_mode",
+    "persistent_sym",
+    "qemu_path",
+    "ld_library_path",
+    "crash_path",
+    "corpus_path",
+    "queue_path",
+    "plot_path",
+    "timeout",
+    "hangs_path",
+    "use_stdin",
+    "shmem_input",
+    "persistent_hook",
+    "input_dir",
+    "max_input_len",
+    "oversize_policy",
+    "state_path",
+    "save_interval",
+    "triage",
+    "triage_depth",
+    "crash_repro_runs",
+    "suppression_path",
+    "suppress_queue",
+    "max_time",
+    "max_execs",
+    "inst_libs",
+    "inst_ranges",
+    "exclude_ranges",
+    "qemu_ld_prefix",
+    "inst_targets",
+    "exclude_targets",
+];
+
+/// One thing wrong with the configuration
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigProblem {
+    /// the config file could not be read or is not a valid ini file
+    Unreadable(String),
+    MissingKey(&'static str),
+    UnknownKey {
+        section: String,
+        key: String,
+    },
+    UnknownSection(String),
+    /// the profile selected on the command line has no section
+    UnknownProfile(String),
+    InvalidValue {
+        key: &'static str,
+        reason: String,
+    },
+    /// a file or directory the fuzzer reads does not exist
+    PathNotFound {
+        key: &'static str,
+        path: PathBuf,
+    },
+    NotExecutable(PathBuf),
+    MapSizeNotPowerOfTwo(usize),
+    /// --resume was given but there is no state to resume from
+    ResumeWithoutStatePath,
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigProblem::Unreadable(e) => write!(f, "can't read the config file: {}", e),
+            ConfigProblem::MissingKey(key) => write!(f, "missing {}", key),
+            ConfigProblem::UnknownKey { section, key } => {
+                write!(f, "unknown key {} in [{}]", key, section)
+            }
+            ConfigProblem::UnknownSection(section) => write!(f, "unknown section [{}]", section),
+            ConfigProblem::UnknownProfile(section) => write!(f, "no section [{}]", section),
+            ConfigProblem::InvalidValue { key, reason } => write!(f, "invalid {}: {}", key, reason),
+            ConfigProblem::PathNotFound { key, path } => {
+                write!(f, "{} {:?} does not exist", key, path)
+            }
+            ConfigProblem::NotExecutable(path) => write!(f, "QEMU {:?} is not executable", path),
+            ConfigProblem::MapSizeNotPowerOfTwo(size) => {
+                write!(f, "map_size {} is not a power of two", size)
+            }
+            ConfigProblem::ResumeWithoutStatePath => write!(f, "--resume needs state_path"),
+        }
+    }
+}
+
+/// Everything wrong with a config file, so it can be fixed in one go
+#[derive(Debug)]
+pub struct ConfigError {
+    pub path: String,
+    pub problems: Vec<ConfigProblem>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} problem(s) in {}", self.problems.len(), self.path)?;
+        for problem in self.problems.iter() {
+            write!(f, "\n  - {}", problem)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Reads config values, a value that doesn't parse is recorded as a problem and replaced with the
+/// default so the rest of the file is still checked.
+///
+/// Every key can be set in three places, the first one found wins:
+/// `FUZZER_<KEY>` in the environment (`FUZZER_QEMU_PATH` for `qemu_path`), the selected
+/// `[profile.<name>]` section and the `[general]` section. the `[qemu_env]` and `[guest_env]`
+/// sections hold environment variables already, set those in the environment of QEMU or in
+/// QEMU_SET_ENV directly. `resume` is not a key, it only comes from `--resume`
+struct Reader<'a> {
+    ini: &'a Ini,
+    /// section of the selected profile
+    profile: Option<String>,
+    problems: Vec<ConfigProblem>,
+}
+
+impl<'a> Reader<'a> {
+    /// the value of `key` and where it came from, for error messages
+    fn lookup(&self, key: &str) -> Option<(String, String)> {
+        let var = env_var_name(key);
+        if let Ok(value) = env::var(&var) {
+            debug!("[*] {} overridden by {}", key, var);
+            return Some((value, var));
+        }
+
+        if let Some(profile) = &self.profile {
+            if let Some(value) = self.ini.get(profile, key) {
+                return Some((value, format!("[{}]", profile)));
+            }
+        }
+
+        self.ini
+            .get(GENERAL_SECTION, key)
+            .map(|value| (value, format!("[{}]", GENERAL_SECTION)))
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        self.lookup(key).map(|(value, _)| value)
+    }
+
+    fn getuint(&mut self, key: &'static str) -> Option<u64> {
+        self.parse(key, "an unsigned integer", |s| s.parse().ok())
+    }
+
+    fn getbool(&mut self, key: &'static str) -> Option<bool> {
+        self.parse(key, "true or false", |s| match s.to_lowercase().as_str() {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        })
+    }
+
+    /// a value with its own parser, like coverage_mode
+    fn parse<T>(
+        &mut self,
+        key: &'static str,
+        expected: &str,
+        parse: fn(&str) -> Option<T>,
+    ) -> Option<T> {
+        let (value, source) = self.lookup(key)?;
+        let parsed = parse(value.trim());
+        if parsed.is_none() {
+            self.problems.push(ConfigProblem::InvalidValue {
+                key,
+                reason: format!("{} in {} is not {}", value, source, expected),
+            });
+        }
+
+        parsed
+    }
+
+    /// all keys of a section as name/value pairs, sorted so the order doesn't change between
+    /// runs. a key without a value is an empty variable
+    fn section(&self, section: &str) -> Vec<(String, String)> {
+        let mut vars: Vec<(String, String)> = self
+            .ini
+            .get_map_ref()
+            .get(section)
+            .map(|keys| {
+                keys.iter()
+                    .map(|(name, value)| (name.clone(), value.clone().unwrap_or_default()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        vars.sort();
+        vars
+    }
+
+    fn check_keys(&mut self) {
+        if let Some(profile) = &self.profile {
+            if !self.ini.get_map_ref().contains_key(profile) {
+                self.problems
+                    .push(ConfigProblem::UnknownProfile(profile.clone()));
+            }
+        }
+
+        for (section, keys) in self.ini.get_map_ref().iter() {
+            // any variable name goes in the environment sections
+            if section == QEMU_ENV_SECTION || section == GUEST_ENV_SECTION {
+                continue;
+            }
+
+            if section != GENERAL_SECTION && !section.starts_with(PROFILE_PREFIX) {
+                self.problems
+                    .push(ConfigProblem::UnknownSection(section.clone()));
+                continue;
+            }
+
+            for key in keys.keys() {
+                if !KNOWN_KEYS.contains(&key.as_str()) {
+                    self.problems.push(ConfigProblem::UnknownKey {
+                        section: section.clone(),
+                        key: key.clone(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// `FUZZER_QEMU_PATH` for `qemu_path`
+fn env_var_name(key: &str) -> String {
+    format!("{}{}", ENV_PREFIX, key.to_uppercase())
+}
 
 #[derive(Debug)]
 pub struct Config {
     /// size of map used for coverage
     pub map_size: usize,
+    /// how the coverage map is interpreted
+    pub coverage_mode: CoverageMode,
     /// name of "main" symbol. this will be used for qemu persistent mode
     pub persistent_sym: String,
     /// path to afl-qemu-trace binary
@@ -17,55 +276,222 @@ pub struct Config {
     pub crash_path: PathBuf,
     /// directory for the initial fuzzing testcases
     pub corpus_path: PathBuf,
-    /// directory in which fuzzer will store interesting inputs
+    /// directory in which fuzzer will store interesting inputs, named like AFL queue entries
     pub queue_path: Option<PathBuf>,
     /// directory to store plot data with fuzzing statistics
     pub plot_path: Option<String>,
+    /// how long a single execution may run before it is considered a hang
+    pub timeout: Duration,
+    /// directory in which fuzzer will store hanging testcases
+    pub hangs_path: Option<PathBuf>,
+    /// deliver testcases through the target's stdin instead of a file passed with @@
+    pub use_stdin: bool,
+    /// deliver testcases through shared memory (`__AFL_SHM_FUZZ_ID`) if the forkserver supports it
+    pub shmem_input: bool,
+    /// library loaded by QEMU with AFL_QEMU_PERSISTENT_HOOK, needed for shared memory input
+    pub persistent_hook: Option<String>,
+    /// directory for the file testcases are written to, for example a tmpfs mount
+    pub input_dir: Option<PathBuf>,
+    /// largest testcase the target gets
+    pub max_input_len: usize,
+    /// what to do with testcases larger than `max_input_len`
+    pub oversize_policy: OversizePolicy,
+    /// directory in which fuzzer saves its coverage and power schedule state for --resume
+    pub state_path: Option<PathBuf>,
+    /// how often the queue and state are saved while fuzzing
+    pub save_interval: Duration,
+    /// replay new crashes with QEMU's exec log and keep one crash per backtrace
+    pub triage: bool,
+    /// number of frames kept in a triage backtrace, the stack hash doesn't depend on it
+    pub triage_depth: usize,
+    /// how many times a new crash is run again before it is saved, 0 to save it right away
+    pub crash_repro_runs: usize,
+    /// file with signatures of known crashes that should not be saved
+    pub suppression_path: Option<PathBuf>,
+    /// also keep suppressed crashes out of the queue
+    pub suppress_queue: bool,
+    /// stop fuzzing after this long
+    pub max_time: Option<Duration>,
+    /// stop fuzzing after this many executions
+    pub max_execs: Option<u64>,
+    /// continue from the state saved in `state_path`, only set from the command line
+    pub resume: bool,
+    /// extra environment of QEMU, from the `[qemu_env]` section
+    pub qemu_env: Vec<(String, String)>,
+    /// extra environment of the target, from the `[guest_env]` section
+    pub guest_env: Vec<(String, String)>,
+    /// instrument shared libraries too (AFL_INST_LIBS)
+    pub inst_libs: bool,
+    /// only instrument these address ranges, `start-end` pairs separated by commas
+    /// (AFL_QEMU_INST_RANGES)
+    pub inst_ranges: Option<String>,
+    /// don't instrument these address ranges (AFL_QEMU_EXCLUDE_RANGES)
+    pub exclude_ranges: Option<String>,
+    /// directory QEMU loads the guest's interpreter and libraries from (QEMU_LD_PREFIX)
+    pub qemu_ld_prefix: Option<PathBuf>,
+    /// functions, sections or ranges of the target to instrument, added to `inst_ranges` once
+    /// they are resolved to guest addresses
+    pub inst_targets: Vec<RangeSpec>,
+    /// parts of the target not to instrument, added to `exclude_ranges`
+    pub exclude_targets: Vec<RangeSpec>,
 }
 
 impl Config {
-    pub fn parse(path: &str) -> Self {
-        let mut config = Ini::new();
-        config.load(path).expect("Error while reading config file");
+    pub fn parse(path: &str) -> Result<Self, ConfigError> {
+        Self::parse_with(path, None, |_| {})
+    }
 
-        let section = "general";
+    /// like `parse`, reading the keys of `[profile.<profile>]` over the ones in `[general]`.
+    /// `overrides` (the command line for example) gets to change the config before it is
+    /// validated
+    pub fn parse_with<F>(
+        path: &str,
+        profile: Option<&str>,
+        overrides: F,
+    ) -> Result<Self, ConfigError>
+    where
+        F: FnOnce(&mut Self),
+    {
+        let error = |problems| ConfigError {
+            path: path.to_string(),
+            problems,
+        };
 
-        let map_size = config
-            .getuint(section, "map_size")
-            .expect("Error parsing configuration")
-            .unwrap_or(DEFAULT_MAP_SIZE) as usize;
+        // case sensitive, keys of the environment sections are variable names
+        let mut ini = Ini::new_cs();
+        if let Err(e) = ini.load(path) {
+            return Err(error(vec![ConfigProblem::Unreadable(e)]));
+        }
 
-        let persistent_sym = config
-            .get(section, "persistent_sym")
-            .unwrap_or("main".to_string());
+        let mut reader = Reader {
+            ini: &ini,
+            profile: profile.map(|p| format!("{}{}", PROFILE_PREFIX, p)),
+            problems: Vec::new(),
+        };
+        reader.check_keys();
 
-        let qemu_path = config
-            .get(section, "qemu_path")
-            .expect("Missing path to QEMU binary");
+        let mut config = Self::read(&mut reader);
+        overrides(&mut config);
 
-        let crash_path = PathBuf::from(
-            config
-                .get(section, "crash_path")
-                .unwrap_or("./crashes".to_string()),
-        );
+        let mut problems = reader.problems;
+        config.validate(&mut problems);
 
-        let corpus_path = PathBuf::from(
-            config
-                .get(section, "corpus_path")
-                .unwrap_or("./corpus".to_string()),
-        );
+        if !problems.is_empty() {
+            return Err(error(problems));
+        }
 
-        let queue_path = if let Some(p) = config.get(section, "queue_path") {
+        Ok(config)
+    }
+
+    fn read(config: &mut Reader) -> Self {
+        let map_size = config.getuint("map_size").unwrap_or(DEFAULT_MAP_SIZE) as usize;
+
+        let coverage_mode = config
+            .parse(
+                "coverage_mode",
+                "bit, byte or hitcount",
+                CoverageMode::parse,
+            )
+            .unwrap_or(CoverageMode::Bits);
+
+        let persistent_sym = config.get("persistent_sym").unwrap_or("main".to_string());
+
+        // checked in `validate`, the command line may still provide it
+        let qemu_path = config.get("qemu_path").unwrap_or_default();
+
+        let crash_path = PathBuf::from(config.get("crash_path").unwrap_or("./crashes".to_string()));
+
+        let corpus_path =
+            PathBuf::from(config.get("corpus_path").unwrap_or("./corpus".to_string()));
+
+        let queue_path = if let Some(p) = config.get("queue_path") {
             Some(PathBuf::from(p))
         } else {
             None
         };
 
-        let plot_path = config.get(section, "plot_path");
-        let ld_library_path = config.get(section, "ld_library_path");
+        let plot_path = config.get("plot_path");
+        let ld_library_path = config.get("ld_library_path");
+
+        let timeout =
+            Duration::from_millis(config.getuint("timeout").unwrap_or(DEFAULT_TIMEOUT_MS));
+
+        let hangs_path = config.get("hangs_path").map(PathBuf::from);
+
+        let use_stdin = config.getbool("use_stdin").unwrap_or(false);
+
+        let shmem_input = config.getbool("shmem_input").unwrap_or(false);
+
+        let persistent_hook = config.get("persistent_hook");
+
+        let input_dir = config.get("input_dir").map(PathBuf::from);
+
+        let max_input_len = config
+            .getuint("max_input_len")
+            .unwrap_or(DEFAULT_MAX_INPUT_LEN) as usize;
+
+        let oversize_policy = config
+            .parse(
+                "oversize_policy",
+                "truncate or reject",
+                OversizePolicy::parse,
+            )
+            .unwrap_or(OversizePolicy::Truncate);
+
+        let state_path = config.get("state_path").map(PathBuf::from);
+
+        let save_interval = Duration::from_secs(
+            config
+                .getuint("save_interval")
+                .unwrap_or(DEFAULT_SAVE_INTERVAL_SECS),
+        );
+
+        let triage = config.getbool("triage").unwrap_or(false);
+
+        let triage_depth = config
+            .getuint("triage_depth")
+            .unwrap_or(DEFAULT_TRIAGE_DEPTH) as usize;
+
+        let crash_repro_runs = config
+            .getuint("crash_repro_runs")
+            .unwrap_or(DEFAULT_CRASH_REPRO_RUNS) as usize;
+
+        let suppression_path = config.get("suppression_path").map(PathBuf::from);
+
+        let suppress_queue = config.getbool("suppress_queue").unwrap_or(false);
+
+        let max_time = config.getuint("max_time").map(Duration::from_secs);
+
+        let max_execs = config.getuint("max_execs");
+
+        let qemu_env = config.section(QEMU_ENV_SECTION);
+        let guest_env = config.section(GUEST_ENV_SECTION);
+
+        let inst_libs = config.getbool("inst_libs").unwrap_or(false);
+
+        let inst_ranges = config.get("inst_ranges");
+        let exclude_ranges = config.get("exclude_ranges");
+        let qemu_ld_prefix = config.get("qemu_ld_prefix").map(PathBuf::from);
+
+        let inst_targets = config
+            .parse(
+                "inst_targets",
+                "a list of sym:, section: or range: entries",
+                RangeSpec::parse_list,
+            )
+            .unwrap_or_default();
+
+        let exclude_targets = config
+            .parse(
+                "exclude_targets",
+                "a list of sym:, section: or range: entries",
+                RangeSpec::parse_list,
+            )
+            .unwrap_or_default();
 
         Self {
             map_size,
+            coverage_mode,
             persistent_sym,
             qemu_path,
             crash_path,
@@ -73,6 +499,249 @@ impl Config {
             queue_path,
             plot_path,
             ld_library_path,
+            timeout,
+            hangs_path,
+            use_stdin,
+            shmem_input,
+            persistent_hook,
+            input_dir,
+            max_input_len,
+            oversize_policy,
+            state_path,
+            save_interval,
+            triage,
+            triage_depth,
+            crash_repro_runs,
+            suppression_path,
+            suppress_queue,
+            max_time,
+            max_execs,
+            resume: false,
+            qemu_env,
+            guest_env,
+            inst_libs,
+            inst_ranges,
+            exclude_ranges,
+            qemu_ld_prefix,
+            inst_targets,
+            exclude_targets,
+        }
+    }
+
+    /// keep every output directory under `dir`, laid out like AFL's output directory
+    pub fn set_output_dir(&mut self, dir: &Path) {
+        self.crash_path = dir.join("crashes");
+        self.hangs_path = Some(dir.join("hangs"));
+        self.queue_path = Some(dir.join("queue"));
+        self.plot_path = Some(dir.join("plots").to_string_lossy().to_string());
+        self.state_path = Some(dir.join("state"));
+    }
+
+    /// environment of every QEMU we start: the instrumentation switches, LD_LIBRARY_PATH and
+    /// the extra variables of both environment sections
+    pub fn qemu_env(&self) -> QemuEnv {
+        let mut env = QemuEnv::new();
+        env.set_inst_libs(self.inst_libs);
+        env.set_inst_ranges(self.inst_ranges.clone());
+        env.set_exclude_ranges(self.exclude_ranges.clone());
+        env.set_ld_prefix(
+            self.qemu_ld_prefix
+                .as_ref()
+                .map(|p| p.to_string_lossy().to_string()),
+        );
+
+        if let Some(ld_library_path) = &self.ld_library_path {
+            env.add_guest_var("LD_LIBRARY_PATH", ld_library_path);
+        }
+
+        for (name, value) in self.guest_env.iter() {
+            env.add_guest_var(name, value);
+        }
+
+        for (name, value) in self.qemu_env.iter() {
+            env.add_qemu_var(name, value);
+        }
+
+        env
+    }
+
+    /// problems that only show up once the values are known: paths that don't exist, a QEMU we
+    /// can't run and sizes the coverage map can't have
+    fn validate(&self, problems: &mut Vec<ConfigProblem>) {
+        if self.qemu_path.is_empty() {
+            problems.push(ConfigProblem::MissingKey("qemu_path"));
+        } else {
+            let qemu_path = PathBuf::from(&self.qemu_path);
+            match qemu_path.metadata() {
+                Ok(meta) if meta.is_file() && meta.permissions().mode() & 0o111 != 0 => {}
+                Ok(_) => problems.push(ConfigProblem::NotExecutable(qemu_path)),
+                Err(_) => problems.push(ConfigProblem::PathNotFound {
+                    key: "qemu_path",
+                    path: qemu_path,
+                }),
+            }
         }
+
+        if !self.corpus_path.is_dir() {
+            problems.push(ConfigProblem::PathNotFound {
+                key: "corpus_path",
+                path: self.corpus_path.clone(),
+            });
+        }
+
+        let inputs = [
+            (
+                "persistent_hook",
+                self.persistent_hook.as_ref().map(PathBuf::from),
+            ),
+            ("input_dir", self.input_dir.clone()),
+            ("suppression_path", self.suppression_path.clone()),
+            ("qemu_ld_prefix", self.qemu_ld_prefix.clone()),
+        ];
+        for (key, path) in inputs.iter() {
+            if let Some(path) = path {
+                if !path.exists() {
+                    problems.push(ConfigProblem::PathNotFound {
+                        key: *key,
+                        path: path.clone(),
+                    });
+                }
+            }
+        }
+
+        // QEMU_SET_ENV is split on commas
+        for (name, value) in self.guest_env.iter() {
+            if value.contains(',') {
+                problems.push(ConfigProblem::InvalidValue {
+                    key: "guest_env",
+                    reason: format!("{} can't contain a comma ({})", name, value),
+                });
+            }
+        }
+
+        if !self.map_size.is_power_of_two() {
+            problems.push(ConfigProblem::MapSizeNotPowerOfTwo(self.map_size));
+        }
+
+        if self.resume && self.state_path.is_none() {
+            problems.push(ConfigProblem::ResumeWithoutStatePath);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, process};
+
+    /// a directory with an executable QEMU and a corpus directory, and a config file whose
+    /// `[general]` section points at them followed by `extra`
+    fn write_config(name: &str, extra: &str) -> (PathBuf, String) {
+        let dir = env::temp_dir().join(format!("config-test-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("corpus")).unwrap();
+
+        let qemu = dir.join("qemu");
+        fs::write(&qemu, "").unwrap();
+        fs::set_permissions(&qemu, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let path = dir.join("config.ini");
+        let contents = format!(
+            "[general]\nqemu_path = {}\ncorpus_path = {}\n{}",
+            qemu.display(),
+            dir.join("corpus").display(),
+            extra
+        );
+        fs::write(&path, contents).unwrap();
+
+        (dir, path.to_string_lossy().to_string())
+    }
+
+    fn problems(path: &str) -> Vec<ConfigProblem> {
+        Config::parse(path).unwrap_err().problems
+    }
+
+    #[test]
+    fn parses_a_valid_config() {
+        let (dir, path) = write_config("valid", "timeout = 250\nuse_stdin = true\n");
+
+        let config = Config::parse(&path).unwrap();
+        assert_eq!(config.timeout, Duration::from_millis(250));
+        assert!(config.use_stdin);
+        assert_eq!(config.corpus_path, dir.join("corpus"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn env_overrides_profile_overrides_general() {
+        let (dir, path) = write_config(
+            "lookup-order",
+            "persistent_sym = general\n[profile.ci]\npersistent_sym = profile\n",
+        );
+
+        let config = Config::parse(&path).unwrap();
+        assert_eq!(config.persistent_sym, "general");
+
+        let config = Config::parse_with(&path, Some("ci"), |_| {}).unwrap();
+        assert_eq!(config.persistent_sym, "profile");
+
+        // no other test reads persistent_sym
+        env::set_var("FUZZER_PERSISTENT_SYM", "env");
+        let config = Config::parse_with(&path, Some("ci"), |_| {}).unwrap();
+        env::remove_var("FUZZER_PERSISTENT_SYM");
+        assert_eq!(config.persistent_sym, "env");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_unknown_keys() {
+        let (dir, path) = write_config("unknown-key", "tiemout = 250\n");
+
+        assert_eq!(
+            problems(&path),
+            vec![ConfigProblem::UnknownKey {
+                section: GENERAL_SECTION.to_string(),
+                key: "tiemout".to_string(),
+            }]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_bad_integers() {
+        let (dir, path) = write_config("bad-integer", "timeout = soon\n");
+
+        match problems(&path).as_slice() {
+            [ConfigProblem::InvalidValue { key, reason }] => {
+                assert_eq!(*key, "timeout");
+                assert!(reason.contains("soon"));
+            }
+            other => panic!("expected an invalid timeout, got {:?}", other),
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_missing_paths() {
+        let (dir, _) = write_config("missing-path", "");
+        let path = dir.join("empty.ini");
+        fs::write(&path, "[general]\ncorpus_path = /nonexistent/corpus\n").unwrap();
+
+        assert_eq!(
+            problems(&path.to_string_lossy()),
+            vec![
+                ConfigProblem::MissingKey("qemu_path"),
+                ConfigProblem::PathNotFound {
+                    key: "corpus_path",
+                    path: PathBuf::from("/nonexistent/corpus"),
+                },
+            ]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -0,0 +1,138 @@
+use log::trace;
+use std::ops::Range;
+
+/// number of AFL hit count buckets, one id per bucket for every map byte
+const BUCKETS: usize = 8;
+
+/// How the bytes of the coverage map are turned into coverage ids
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CoverageMode {
+    /// every bit of every map byte is a separate edge
+    Bits,
+    /// every non zero map byte is an edge, this matches the edge count of afl-showmap
+    Bytes,
+    /// every map byte is an edge hit counter (like afl-qemu-trace writes it). the counter is
+    /// classified into AFL's hit count buckets and every bucket gets its own id, so a loop
+    /// running a different number of times counts as new coverage
+    HitCounts,
+}
+
+impl CoverageMode {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "bit" => Some(CoverageMode::Bits),
+            "byte" => Some(CoverageMode::Bytes),
+            "hitcount" => Some(CoverageMode::HitCounts),
+            _ => None,
+        }
+    }
+
+    /// number of distinct coverage ids a map of `map_size` bytes can produce, this is the size
+    /// the all-time coverage state needs
+    pub fn state_size(&self, map_size: usize) -> usize {
+        match self {
+            CoverageMode::Bits => map_size * 8,
+            CoverageMode::Bytes => map_size,
+            CoverageMode::HitCounts => map_size * BUCKETS,
+        }
+    }
+
+    /// push the coverage ids found in a single map byte into `coverage`
+    pub fn visit_byte(&self, byte_index: usize, value: u8, coverage: &mut Vec<usize>) {
+        // no coverage for this index
+        if value == 0 {
+            return;
+        }
+
+        match self {
+            CoverageMode::Bits => {
+                // we found coverage in this index. figure out which bit is turned on
+                for bit_index in 0..8 as u8 {
+                    let mask: u8 = 1 << bit_index;
+                    if value & mask != 0 {
+                        let basic_block_id = byte_index * 8 + bit_index as usize;
+                        trace!("push coverage id {}", basic_block_id);
+                        coverage.push(basic_block_id);
+                    }
+                }
+            }
+            CoverageMode::Bytes => {
+                trace!("push coverage id {}", byte_index);
+                coverage.push(byte_index);
+            }
+            CoverageMode::HitCounts => {
+                let id = byte_index * BUCKETS + hit_count_bucket(value);
+                trace!("push coverage id {} (hits {})", id, value);
+                coverage.push(id);
+            }
+        }
+    }
+
+    /// all coverage ids that belong to the same map byte as `id`. with hit counts an id
+    /// is a bucket of an edge, so this is how we tell a new edge from a new hit count
+    pub fn edge_ids(&self, id: usize) -> Range<usize> {
+        match self {
+            CoverageMode::Bits | CoverageMode::Bytes => id..id + 1,
+            CoverageMode::HitCounts => {
+                let first = id - id % BUCKETS;
+                first..first + BUCKETS
+            }
+        }
+    }
+
+    /// collect the coverage ids of a whole map
+    pub fn collect(&self, map: &[u8]) -> Vec<usize> {
+        let mut coverage = Vec::new();
+        for (i, value) in map.iter().enumerate() {
+            self.visit_byte(i, *value, &mut coverage);
+        }
+
+        coverage
+    }
+}
+
+/// index of the AFL hit count bucket for a non zero hit count.
+/// buckets are 1, 2, 3, 4-7, 8-15, 16-31, 32-127, 128+
+pub fn hit_count_bucket(hits: u8) -> usize {
+    match hits {
+        0 | 1 => 0,
+        2 => 1,
+        3 => 2,
+        4..=7 => 3,
+        8..=15 => 4,
+        16..=31 => 5,
+        32..=127 => 6,
+        128..=255 => 7,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_hit_counts_like_afl() {
+        let buckets: Vec<usize> = [1, 2, 3, 4, 7, 8, 15, 16, 31, 32, 127, 128, 255]
+            .iter()
+            .map(|hits| hit_count_bucket(*hits))
+            .collect();
+        assert_eq!(buckets, vec![0, 1, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7]);
+    }
+
+    #[test]
+    fn collects_ids_per_mode() {
+        let map = [0, 0b101, 0, 9];
+        assert_eq!(CoverageMode::Bits.collect(&map), vec![8, 10, 24, 27]);
+        assert_eq!(CoverageMode::Bytes.collect(&map), vec![1, 3]);
+        assert_eq!(CoverageMode::HitCounts.collect(&map), vec![11, 28]);
+    }
+
+    #[test]
+    fn edge_ids_cover_every_bucket_of_an_edge() {
+        assert_eq!(CoverageMode::HitCounts.edge_ids(0), 0..8);
+        assert_eq!(CoverageMode::HitCounts.edge_ids(11), 8..16);
+        assert_eq!(CoverageMode::HitCounts.edge_ids(15), 8..16);
+        assert_eq!(CoverageMode::Bytes.edge_ids(11), 11..12);
+        assert_eq!(CoverageMode::Bits.edge_ids(11), 11..12);
+    }
+}
@@ -1,7 +1,8 @@
 use log::{trace, debug};
 
+use std::ops::Range;
 use std::path::Path;
-use goblin::Object;
+use goblin::{elf::section_header::SHN_UNDEF, Object};
 use std::fs;
 
 pub fn find_addr_by_sym(bin: &str, sym_name: &str) -> Result<u64, goblin::error::Error> {
@@ -29,3 +30,112 @@ pub fn find_addr_by_sym(bin: &str, sym_name: &str) -> Result<u64, goblin::error:
         "Coud not find symbol".to_string(),
     ));
 }
+
+/// the target is position independent and QEMU maps it at its load base
+pub fn is_pie(bin: &str) -> Result<bool, goblin::error::Error> {
+    let buffer = fs::read(Path::new(bin))?;
+
+    if let Object::Elf(elf) = Object::parse(&buffer)? {
+        return Ok(elf.header.e_type == goblin::elf::header::ET_DYN);
+    }
+
+    Err(goblin::error::Error::Malformed(
+        "Binary is not an elf".to_string(),
+    ))
+}
+
+/// where QEMU maps the target: `pie_base` for a position independent target, nothing is added
+/// to the addresses of other targets
+pub fn load_base(bin: &str, pie_base: u64) -> Result<u64, goblin::error::Error> {
+    Ok(if is_pie(bin)? { pie_base } else { 0 })
+}
+
+/// address range of a function, looked up in the dynamic symbols and, for static functions,
+/// the symbol table if the binary isn't stripped
+pub fn find_sym_range(bin: &str, sym_name: &str) -> Result<Range<u64>, goblin::error::Error> {
+    let buffer = fs::read(Path::new(bin))?;
+
+    // a match without a size may still have one in the other table
+    let mut no_size = false;
+
+    if let Object::Elf(elf) = Object::parse(&buffer)? {
+        let tables = [(&elf.dynsyms, &elf.dynstrtab), (&elf.syms, &elf.strtab)];
+        for (syms, strtab) in tables.iter() {
+            for sym in syms.iter() {
+                if strtab.get(sym.st_name).and_then(|n| n.ok()) != Some(sym_name) {
+                    continue;
+                }
+
+                // imported from a library, the function is not in this binary
+                if sym.st_shndx == SHN_UNDEF as usize {
+                    continue;
+                }
+
+                if sym.st_size == 0 {
+                    no_size = true;
+                    continue;
+                }
+
+                debug!(
+                    "found symbol {} in bin {} at {:#x} size {:#x}",
+                    sym_name, bin, sym.st_value, sym.st_size
+                );
+                return Ok(sym.st_value..sym.st_value + sym.st_size);
+            }
+        }
+    } else {
+        return Err(goblin::error::Error::Malformed(
+            "Binary is not an elf".to_string(),
+        ));
+    }
+
+    if no_size {
+        return Err(goblin::error::Error::Malformed(format!(
+            "Symbol {} has no size",
+            sym_name
+        )));
+    }
+
+    Err(goblin::error::Error::Malformed(format!(
+        "Could not find symbol {}",
+        sym_name
+    )))
+}
+
+/// address range of a section that is loaded into memory, like `.text`
+pub fn find_section_range(
+    bin: &str,
+    section_name: &str,
+) -> Result<Range<u64>, goblin::error::Error> {
+    let buffer = fs::read(Path::new(bin))?;
+
+    if let Object::Elf(elf) = Object::parse(&buffer)? {
+        for section in elf.section_headers.iter() {
+            if elf.shdr_strtab.get(section.sh_name).and_then(|n| n.ok()) != Some(section_name) {
+                continue;
+            }
+
+            if section.sh_addr == 0 {
+                return Err(goblin::error::Error::Malformed(format!(
+                    "Section {} is not loaded",
+                    section_name
+                )));
+            }
+
+            debug!(
+                "found section {} in bin {} at {:#x} size {:#x}",
+                section_name, bin, section.sh_addr, section.sh_size
+            );
+            return Ok(section.sh_addr..section.sh_addr + section.sh_size);
+        }
+    } else {
+        return Err(goblin::error::Error::Malformed(
+            "Binary is not an elf".to_string(),
+        ));
+    }
+
+    Err(goblin::error::Error::Malformed(format!(
+        "Could not find section {}",
+        section_name
+    )))
+}
@@ -1,16 +1,21 @@
 // #[cfg(target_family = "unix")]
 // use std::os::unix::process::ExitStatusExt;
 
-use core::marker::PhantomData;
-use std::process::Stdio;
-use std::{
-    process::{Child, Command},
-    thread,
+use core::{
+    cell::{Ref, RefCell, RefMut},
+    marker::PhantomData,
 };
+use std::fs::File;
+use std::rc::Rc;
+use std::os::raw::c_int;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
 
 use libafl::{
     corpus::Corpus,
-    events::{EventFirer, EventRestarter},
+    events::{Event, EventFirer, EventRestarter},
     executors::{
         Executor, ExitKind, HasExecHooksTuple, HasObservers, HasObserversHooks,
     },
@@ -18,97 +23,189 @@ use libafl::{
     fuzzer::HasObjective,
     inputs::{HasTargetBytes, Input},
     observers::ObserversTuple,
-    state::HasSolutions,
+    state::{HasExecutions, HasSolutions},
+    stats::UserStats,
     Error,
 };
 
 use crate::{
-    outfile::OutFile,
+    executor::handshake::{
+        autodict_len, parse_autodict, ForkserverOptions, FS_OPT_AUTODICT, FS_OPT_ENABLED,
+        FS_OPT_SHDMEM_FUZZ,
+    },
+    executor::qemu_env::QemuEnv,
+    executor::status::ChildStatus,
+    observer::{ExitStatusObserver, EXIT_STATUS_ID},
+    outfile::{OutFile, OversizePolicy},
+    limits::{Limit, RunLimits},
     pipe::Pipe,
+    shmem_input::{ShMemInput, MAX_LEN_ENV_VAR},
 };
 
 // use hexdump;
 use log::{debug, info, log_enabled, warn, Level};
-use std::sync::mpsc::{channel, Receiver, Sender};
-use std::sync::{Arc, Mutex};
 
 // taken from qemuafl/imported/config.h
-const FORKSRV_FD: i32 = 198;
+pub(crate) const FORKSRV_FD: i32 = 198;
+
+/// how long a forkserver may take to send its autodict
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// how often we check on QEMU when the kernel has no pidfd support
+const QEMU_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// name of the user stat counting executions that hit the timeout
+pub const HANGS_STAT: &str = "hangs";
+/// name of the user stat counting how many times QEMU had to be restarted
+pub const RESPAWNS_STAT: &str = "qemu_respawns";
+
+/// outcome of waiting on the forkserver status pipe
+#[derive(Debug, PartialEq)]
+pub enum StatusRead {
+    /// a status word was read from the forkserver
+    Status(i32),
+    /// the forkserver (QEMU) process died
+    Dead,
+    /// nothing arrived before the deadline
+    Timeout,
+}
+
+/// open a file descriptor that becomes readable once the process exits. returns None if the
+/// kernel does not support pidfd_open (added in linux 5.3)
+fn pidfd_open(pid: u32) -> Option<c_int> {
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0) };
+    if fd < 0 {
+        debug!("pidfd_open failed: {}", std::io::Error::last_os_error());
+        return None;
+    }
+
+    Some(fd as c_int)
+}
 
 pub struct Forkserver {
     qemu: String,
     target: String,
-    ld_library_path: String,
+    qemu_env: QemuEnv,
     afl_persistent_addr: Option<String>,
+    args: Vec<String>,
+    /// file that QEMU (and the target) gets as stdin
+    stdin: Option<File>,
+    /// library passed to QEMU as AFL_QEMU_PERSISTENT_HOOK
+    persistent_hook: Option<String>,
+    /// we can deliver testcases through shared memory if the forkserver asks for it
+    shmem_input_supported: bool,
+    /// the forkserver agreed to read testcases from shared memory
+    shmem_input_enabled: bool,
+    /// options announced by the forkserver in the last handshake
+    options: ForkserverOptions,
+    /// tokens of the automatic dictionary the target sent us, if any
+    autodict: Option<Vec<Vec<u8>>>,
 
-    status_pipe: Arc<Pipe>,
+    status_pipe: Pipe,
     control_pipe: Pipe,
 
+    /// the running QEMU process
+    qemu_process: Option<Child>,
+    /// readable once QEMU exits, None if the kernel has no pidfd support
+    qemu_pidfd: Option<c_int>,
+
     pid: u32,       // pid of forkserver. this is the father which children will fork from
     child_pid: i32, // pid of fuzzed program (our grand child)
     status: i32,
     is_qemu_alive: bool,
-
-    child_status_sender: Sender<i32>,
-    child_status_receiver: Arc<Mutex<Receiver<i32>>>,
+    respawns: u64,
 }
 
 impl Forkserver {
-    pub fn new(qemu: String, ld_library_path: String, target: String) -> Self {
+    pub fn new(qemu: String, qemu_env: QemuEnv, target: String) -> Result<Self, Error> {
         // NAME | Who | R/W   | ID
         // -------------------------
         // CTL  | AFL | Read  | 198
         // CTL  | Us  | Write | Anon
         // ST   | AFL | Write | 199
         // ST   | Us  | Read  | Anon
-        let mut control_pipe = Pipe::new("control_pipe".to_owned());
-        let mut status_pipe = Pipe::new("status_pipe".to_owned());
+        let mut control_pipe = Pipe::new("control_pipe".to_owned())?;
+        let mut status_pipe = Pipe::new("status_pipe".to_owned())?;
 
         // pass down to afl CTL:read and ST:write
-        control_pipe.dup_read(FORKSRV_FD);
-        status_pipe.dup_write(FORKSRV_FD + 1);
+        control_pipe.dup_read(FORKSRV_FD)?;
+        status_pipe.dup_write(FORKSRV_FD + 1)?;
 
-        // multiplex child exit events and forkserver status messages to a single incoming pipe
-        // this way we can avoid blocking forever if the forkserver crashes
-        let (sender, receiver) = channel();
-
-        Self {
+        Ok(Self {
             qemu,
             target,
-            ld_library_path,
+            qemu_env,
             afl_persistent_addr: None,
+            args: Vec::new(),
+            stdin: None,
+            persistent_hook: None,
+            shmem_input_supported: false,
+            shmem_input_enabled: false,
+            options: ForkserverOptions::default(),
+            autodict: None,
+            status_pipe,
+            control_pipe,
+            qemu_process: None,
+            qemu_pidfd: None,
             pid: 0,
             child_pid: 0,
             status: 0,
             is_qemu_alive: false,
-            status_pipe: Arc::new(status_pipe),
-            control_pipe,
-            child_status_sender: sender,
-            child_status_receiver: Arc::new(Mutex::new(receiver)),
-        }
+            respawns: 0,
+        })
     }
 
     pub fn set_persistent_addr(&mut self, addr: String) {
         self.afl_persistent_addr = Some(addr);
     }
 
+    /// deliver testcases through stdin. the file descriptor is inherited by every child so the
+    /// file must be rewound before each run
+    pub fn set_stdin(&mut self, file: File) {
+        self.stdin = Some(file);
+    }
+
+    pub fn set_persistent_hook(&mut self, path: String) {
+        self.persistent_hook = Some(path);
+    }
+
+    /// accept shared memory testcase delivery if the forkserver offers it in the handshake.
+    /// the shared memory itself must already be exported with `__AFL_SHM_FUZZ_ID`
+    pub fn set_shmem_input_supported(&mut self, supported: bool) {
+        self.shmem_input_supported = supported;
+    }
+
+    /// true if the last handshake enabled shared memory testcase delivery
+    pub fn shmem_input_enabled(&self) -> bool {
+        self.shmem_input_enabled
+    }
+
+    /// coverage map size reported by the forkserver, if it reported one
+    pub fn map_size(&self) -> Option<usize> {
+        self.options.map_size
+    }
+
+    /// tokens of the automatic dictionary received during the handshake
+    pub fn autodict(&self) -> Option<&Vec<Vec<u8>>> {
+        self.autodict.as_ref()
+    }
+
     pub fn start(&mut self, args: Vec<String>) {
         if self.is_qemu_alive {
             panic!("Cannot start a new qemu server while one is still running");
         }
 
-        // input pipe --> sender channel
-        Forkserver::async_collect_status_pipe(self.child_status_sender.clone(), self.status_pipe.clone());
         self.restart(args);
     }
 
     pub fn restart(&mut self, args: Vec<String>) {
+        self.args = args.clone();
+
         let child = self.run_qemu(args);
         let pid = child.id();
 
-        // signal --> sender channel
-        Forkserver::update_on_child_exit(child, self.child_status_sender.clone());
-
+        self.qemu_pidfd = pidfd_open(pid);
+        self.qemu_process = Some(child);
         self.pid = pid;
         self.is_qemu_alive = true;
         self.status = 0;
@@ -119,7 +216,14 @@ impl Forkserver {
         let mut cmd = Command::new(self.qemu.clone());
         cmd.arg(self.target.clone());
         cmd.args(args);
-        cmd.env("QEMU_SET_ENV", &format!("LD_LIBRARY_PATH={}", self.ld_library_path));
+        self.qemu_env.apply(&mut cmd);
+
+        if let Some(stdin) = &self.stdin {
+            let f = stdin.try_clone().expect("Failed to duplicate stdin file");
+            cmd.stdin(Stdio::from(f));
+        } else {
+            cmd.stdin(Stdio::null());
+        }
 
         if log_enabled!(Level::Debug){
             cmd.env("AFL_DEBUG", "1");
@@ -134,7 +238,20 @@ impl Forkserver {
             cmd.env("AFL_QEMU_PERSISTENT_ADDR", persistent_addr); // 0x5500000000 + $(nm --dynamic | grep main)
         }
 
-        // cmd.env("AFL_INST_LIBS", "1"); // TODO make configurable
+        if let Some(hook) = &self.persistent_hook {
+            cmd.env("AFL_QEMU_PERSISTENT_HOOK", hook);
+        }
+
+        // QEMU and the targets it forks get their own process group, so a Ctrl-C in the
+        // terminal reaches only us and we can kill all of them at once when we are done
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setpgid(0, 0) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
 
         let mut child = cmd.spawn().expect("Failed to run QEMU"); // start AFL ForkServer in QEMU mode in different process
         if let Ok(Some(exit_status)) = child.try_wait() {
@@ -144,69 +261,516 @@ impl Forkserver {
         return child;
     }
 
-    pub fn do_handshake(&self) {
-        self.try_read_status();
+    /// read the hello message of a freshly started forkserver and answer the options it
+    /// announced
+    pub fn do_handshake(&mut self) -> Result<(), Error> {
+        self.shmem_input_enabled = false;
+
+        let hello = match self.try_read_status()? {
+            Some(hello) => hello as u32,
+            None => {
+                warn!("[!] forkserver died during handshake");
+                return Ok(());
+            }
+        };
+
+        // this can be seen using AFL_DEBUG=1 and observing the value logged by
+        // Debug: Sending status c00007ff
+        debug!("Received start status {:#x}", hello);
+
+        let options = ForkserverOptions::from_hello(hello)?;
+        debug!("forkserver options {:?}", options);
+
+        if options.shmem_fuzz {
+            if !self.shmem_input_supported {
+                return Err(Error::IllegalState(
+                    "target requested shared memory fuzzing, but it is disabled".to_string(),
+                ));
+            }
+
+            self.shmem_input_enabled = true;
+            info!("[+] forkserver reads testcases from shared memory");
+
+            // with an autodict the shared memory flag is part of the autodict answer
+            if !options.autodict {
+                self.control_pipe
+                    .write_i32((FS_OPT_ENABLED | FS_OPT_SHDMEM_FUZZ) as i32)?;
+            }
+        }
+
+        if let Some(map_size) = options.map_size {
+            info!("[+] target reports a coverage map of {} bytes", map_size);
+        }
+
+        if options.autodict {
+            let mut answer = FS_OPT_ENABLED | FS_OPT_AUTODICT;
+            if self.shmem_input_enabled {
+                answer |= FS_OPT_SHDMEM_FUZZ;
+            }
+            self.control_pipe.write_i32(answer as i32)?;
+
+            let len = match self.read_status_timeout(HANDSHAKE_TIMEOUT)? {
+                StatusRead::Status(len) => autodict_len(len as u32)?,
+                StatusRead::Timeout => {
+                    return Err(Error::IllegalState(
+                        "forkserver did not send its autodict in time".to_string(),
+                    ))
+                }
+                StatusRead::Dead => {
+                    warn!("[!] forkserver died while sending autodict");
+                    return Ok(());
+                }
+            };
+
+            let data = self
+                .status_pipe
+                .read_bytes_timeout(len, HANDSHAKE_TIMEOUT)?;
+            let tokens = parse_autodict(&data);
+            info!("[+] received autodict with {} tokens", tokens.len());
+            self.autodict = Some(tokens);
+        }
+
+        self.options = options;
         info!("[+] forkserver is alive!");
+        Ok(())
+    }
+
+    pub fn is_qemu_alive(&self) -> bool {
+        self.is_qemu_alive
+    }
+
+    /// number of times QEMU was restarted after it died
+    pub fn respawns(&self) -> u64 {
+        self.respawns
+    }
+
+    /// start a new QEMU after the old one died, counted in `respawns`
+    pub fn respawn(&mut self) -> Result<(), Error> {
+        info!("[!] respawning QEMU forkserver");
+        self.respawns += 1;
+        self.reload()
+    }
+
+    /// throw away the current QEMU (if it is still around) and start a new one with the same
+    /// arguments, including the handshake. used when QEMU has to pick up a new environment
+    pub fn reload(&mut self) -> Result<(), Error> {
+        self.kill_qemu();
+        self.drain_pipes()?;
+
+        self.child_pid = 0;
+        self.restart(self.args.clone());
+        self.do_handshake()
+    }
+
+    /// kill QEMU with its whole process group and wait for it, so nothing of the old process
+    /// (or a target it forked) is left behind
+    pub fn kill_qemu(&mut self) {
+        if let Some(mut qemu) = self.qemu_process.take() {
+            debug!("[!] killing QEMU {}", self.pid);
+            // QEMU leads its process group, see `run_qemu`
+            if unsafe { libc::kill(-(self.pid as libc::pid_t), libc::SIGKILL) } != 0 {
+                debug!("failed killing QEMU process group: {}", std::io::Error::last_os_error());
+                if let Err(e) = qemu.kill() {
+                    debug!("failed killing QEMU: {}", e);
+                }
+            }
+
+            match qemu.wait() {
+                Ok(status) => debug!("QEMU is done. status={}", status),
+                Err(e) => warn!("Error while waiting for QEMU to finish: {}", e),
+            }
+        }
+
+        self.close_pidfd();
+        self.is_qemu_alive = false;
+    }
+
+    fn close_pidfd(&mut self) {
+        if let Some(fd) = self.qemu_pidfd.take() {
+            unsafe {
+                libc::close(fd);
+            }
+        }
     }
 
-    /// wait for a `process::Child` to exit and send it's status code on a `Sender` channel
-    /// sends the status code negated on the channel
-    /// this function does not block
-    fn update_on_child_exit(mut child: Child, sender: Sender<i32>) {
-        thread::spawn(move || {
-            debug!("[!] update_on_child_exit: new thread");
+    /// drop anything a dead forkserver left in the status pipe and any fork request it never
+    /// read from the control pipe. we keep both ends of both pipes open, so a new QEMU would
+    /// otherwise read them as its handshake answer or fork request
+    fn drain_pipes(&mut self) -> Result<(), Error> {
+        while self.status_pipe.poll_read(Duration::from_millis(0))? {
+            let v = self.status_pipe.read_i32()?;
+            debug!("dropping stale status {}", v);
+        }
 
-            let status = child
-                .wait()
-                .expect("Error while waiting for QEMU to finish");
+        while self.control_pipe.poll_read(Duration::from_millis(0))? {
+            let v = self.control_pipe.read_i32()?;
+            debug!("dropping unread control message {}", v);
+        }
 
-            debug!("Child is done. status={}", status);
+        Ok(())
+    }
 
-            let code = status.code().unwrap();
-            assert!(code >= 0);
-            sender.send(-code).unwrap();
+    /// reap QEMU if it exited. returns true if it is gone
+    fn check_qemu_exited(&mut self) -> Result<bool, Error> {
+        let exited = match &mut self.qemu_process {
+            Some(qemu) => qemu.try_wait()?,
+            None => return Ok(true),
+        };
 
-            debug!("[*] update_on_child_exit is done");
-        });
+        if let Some(status) = exited {
+            info!("QEMU exited. status={}", status);
+            self.qemu_process = None;
+            self.close_pidfd();
+            self.is_qemu_alive = false;
+            return Ok(true);
+        }
+
+        Ok(false)
     }
 
-    /// collect all messages from an input Pipe and channel them to a `Sender` channel
-    pub fn async_collect_status_pipe(output: Sender<i32>, input: Arc<Pipe>) {
-        thread::spawn(move || {
-            let mut first = true;
-            loop {
-                let v = input.read_i32();
-                if first {
-                    // this can be seen using AFL_DEBUG=1 and observing the value logged by 
-                    // Debug: Sending status c00007ff
-                    debug!("Received start status {}", v); 
-                    output.send(0).unwrap();
-                    first = false;
+    /// wait for the next forkserver status, QEMU exiting or the deadline, whatever comes first.
+    /// waits forever if there is no deadline
+    fn wait_status(&mut self, timeout: Option<Duration>) -> Result<StatusRead, Error> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+
+        loop {
+            let mut fds = vec![libc::pollfd {
+                fd: self.status_pipe.read_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            }];
+
+            if let Some(pidfd) = self.qemu_pidfd {
+                fds.push(libc::pollfd {
+                    fd: pidfd,
+                    events: libc::POLLIN,
+                    revents: 0,
+                });
+            }
+
+            // without a pidfd we can only find out QEMU died by checking on it periodically
+            let mut wait = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+            if self.qemu_pidfd.is_none() {
+                wait = Some(wait.map_or(QEMU_POLL_INTERVAL, |w| w.min(QEMU_POLL_INTERVAL)));
+            }
+            let wait_ms = wait.map_or(-1, |w| w.as_millis() as c_int);
+
+            let ret = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, wait_ms) };
+            if ret < 0 {
+                let err = std::io::Error::last_os_error();
+                if err.kind() == std::io::ErrorKind::Interrupted {
                     continue;
                 }
+                return Err(err.into());
+            }
 
-                output.send(v).unwrap();
-                debug!("drink_status_pipe loop. read_i32() = {}", v);
+            // a status written right before QEMU exited is still worth reading
+            if fds[0].revents & libc::POLLIN != 0 {
+                return Ok(StatusRead::Status(self.status_pipe.read_i32()?));
             }
-        });
+
+            if self.check_qemu_exited()? {
+                return Ok(StatusRead::Dead);
+            }
+
+            if let Some(deadline) = deadline {
+                if Instant::now() >= deadline {
+                    return Ok(StatusRead::Timeout);
+                }
+            }
+        }
     }
 
-    /// try read forkserver status from status channel
+    /// try read forkserver status from the status pipe
     /// if forkserver died returns None
-    pub fn try_read_status(&self) -> Option<i32> {
-        let status = self
-            .child_status_receiver
-            .lock()
-            .expect("Error taking lock for status receiver")
-            .recv()
-            .expect("Error reading from status receiver");
+    pub fn try_read_status(&mut self) -> Result<Option<i32>, Error> {
+        match self.wait_status(None)? {
+            StatusRead::Status(status) => Ok(Some(status)),
+            _ => Ok(None),
+        }
+    }
 
-        if status >= 0 {
-            return Some(status);
+    /// like `try_read_status` but gives up after `timeout`
+    pub fn read_status_timeout(&mut self, timeout: Duration) -> Result<StatusRead, Error> {
+        self.wait_status(Some(timeout))
+    }
+
+    /// kill the currently running target (our grand child). the forkserver will notice the
+    /// child died and report its status back on the status pipe
+    pub fn kill_child(&self) {
+        if self.child_pid <= 0 {
+            return;
         }
 
-        info!("Got error from status pipe. error: {}", -status);
-        return None;
+        debug!("[!] killing child {}", self.child_pid);
+        unsafe {
+            libc::kill(self.child_pid, libc::SIGKILL);
+        }
+    }
+}
+
+impl Drop for Forkserver {
+    fn drop(&mut self) {
+        self.kill_child();
+        self.kill_qemu();
+    }
+}
+
+/// what happened to a testcase run through a `ForkserverRunner`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunResult {
+    /// the target exited or was killed by a signal
+    Status(ChildStatus),
+    /// QEMU died with the target, the real status is unknown
+    Dead,
+    /// the target hit the timeout and was killed
+    Timeout,
+    /// the testcase is too large and the oversize policy is `Reject`
+    Rejected,
+}
+
+/// Delivers a testcase to the forkserver and waits for the result. The executor shares it with
+/// feedbacks that have to run a testcase again, like `ReproducedCrashFeedback`
+pub struct ForkserverRunner {
+    target: String,
+    args: Vec<String>,
+    use_stdin: bool,
+    out_file: OutFile,
+    /// used instead of `out_file` once the forkserver agreed to shared memory delivery
+    shmem_input: Option<ShMemInput>,
+    oversize_policy: OversizePolicy,
+    forkserver: Forkserver,
+    /// max time a single execution may take before the target is killed
+    timeout: Duration,
+    /// checked by everyone running testcases, the executor and the feedbacks re-running them
+    limits: RunLimits,
+    /// the limit that made us refuse to run a testcase
+    limit_reached: Option<Limit>,
+}
+
+impl ForkserverRunner {
+    /// start QEMU with the forkserver and do the handshake
+    pub fn new(
+        qemu: &str,
+        mut qemu_env: QemuEnv,
+        afl_persistent_addr: Option<String>,
+        persistent_hook: Option<String>,
+        bin: &str,
+        argv: Vec<String>,
+        out_file: OutFile,
+        use_stdin: bool,
+        use_shmem_input: bool,
+        timeout: Duration,
+    ) -> Result<Self, Error> {
+        let target = bin.to_string();
+        let out_filename = out_file
+            .path()
+            .to_str()
+            .expect("Invalid input file path")
+            .to_string();
+        let max_len = out_file.max_len();
+        let args = parse_argv(&argv, &out_filename);
+
+        if use_shmem_input {
+            // the persistent hook copies testcases into a guest buffer, tell it how long they get
+            qemu_env.add_qemu_var(MAX_LEN_ENV_VAR, &max_len.to_string());
+        }
+
+        let mut forkserver = Forkserver::new(
+            qemu.to_string(),
+            qemu_env,
+            bin.to_string())?;
+
+        if let Some(persistent_addr) = afl_persistent_addr {
+            forkserver.set_persistent_addr(persistent_addr);
+        }
+
+        if let Some(hook) = persistent_hook {
+            forkserver.set_persistent_hook(hook);
+        }
+
+        if use_stdin {
+            forkserver.set_stdin(out_file.try_clone_file()?);
+        }
+
+        // the shared memory has to be exported before QEMU starts so it inherits the env
+        let shmem_input = if use_shmem_input {
+            Some(ShMemInput::new(max_len as usize)?)
+        } else {
+            None
+        };
+        forkserver.set_shmem_input_supported(shmem_input.is_some());
+
+        forkserver.start(args.clone());
+        forkserver.do_handshake()?;
+
+        if shmem_input.is_some() && !forkserver.shmem_input_enabled() {
+            warn!("[!] forkserver did not enable shared memory input, falling back to files");
+        }
+
+        Ok(Self {
+            target,
+            args,
+            use_stdin,
+            out_file,
+            shmem_input,
+            oversize_policy: OversizePolicy::Truncate,
+            forkserver,
+            timeout,
+            limits: RunLimits::unlimited(),
+            limit_reached: None,
+        })
+    }
+
+    pub fn set_oversize_policy(&mut self, policy: OversizePolicy) {
+        self.oversize_policy = policy;
+    }
+
+    pub fn forkserver(&self) -> &Forkserver {
+        &self.forkserver
+    }
+
+    pub fn mut_forkserver(&mut self) -> &mut Forkserver {
+        &mut self.forkserver
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn set_limits(&mut self, limits: RunLimits) {
+        self.limits = limits;
+    }
+
+    /// the limit reached before execution number `executions + 1`, if any. once one is
+    /// reached nothing should be run anymore
+    pub fn check_limits(&mut self, executions: usize) -> Option<Limit> {
+        let limit = self.limits.check(executions);
+        if limit.is_some() {
+            self.limit_reached = limit;
+        }
+
+        limit
+    }
+
+    pub fn limit_reached(&self) -> Option<Limit> {
+        self.limit_reached
+    }
+
+    /// start a new QEMU after the old one died
+    fn respawn_forkserver(&mut self) -> Result<(), Error> {
+        self.forkserver.respawn()?;
+        if !self.forkserver.is_qemu_alive() {
+            return Err(Error::IllegalState(
+                "QEMU forkserver died right after respawning".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// ask the forkserver for a new child and record its pid. if the forkserver turns out to be
+    /// dead it is respawned and asked again
+    fn spawn_child(&mut self) -> Result<(), Error> {
+        // QEMU may have died since the last run, a fork request written now would be left in
+        // the control pipe
+        if self.forkserver.check_qemu_exited()? {
+            self.respawn_forkserver()?;
+        }
+
+        self.forkserver.control_pipe.write_i32(0)?;
+        debug!("[+] sent alive signal to child");
+
+        if let Some(child_pid) = self.forkserver.try_read_status()? {
+            debug!("[+] child pid {}", child_pid);
+            self.forkserver.child_pid = child_pid;
+            return Ok(());
+        }
+
+        warn!("[!] forkserver died before forking a child");
+        self.respawn_forkserver()?;
+
+        self.forkserver.control_pipe.write_i32(0)?;
+        if let Some(child_pid) = self.forkserver.try_read_status()? {
+            debug!("[+] child pid {}", child_pid);
+            self.forkserver.child_pid = child_pid;
+            return Ok(());
+        }
+
+        Err(Error::IllegalState("forkserver is misbehaving".to_string()))
+    }
+
+    /// write the testcase where the target reads it from. returns false if it was rejected
+    fn write_input(&mut self, buf: &[u8]) -> bool {
+        let max_len = self.out_file.max_len() as usize;
+        if buf.len() > max_len {
+            if self.oversize_policy == OversizePolicy::Reject {
+                debug!("[-] rejecting testcase of {} bytes, max is {}", buf.len(), max_len);
+                return false;
+            }
+
+            debug!("[-] truncating testcase of {} bytes to {}", buf.len(), max_len);
+        }
+
+        match &mut self.shmem_input {
+            // a respawned forkserver negotiates again, so check on every run
+            Some(shmem_input) if self.forkserver.shmem_input_enabled() => {
+                shmem_input.write_buf(buf);
+            }
+            _ => {
+                // write new testcase to input file
+                self.out_file.write_buf(&buf.to_vec());
+
+                // in stdin mode the target reads from the same file offset we just wrote to
+                if self.use_stdin {
+                    self.out_file.rewind();
+                }
+            }
+        }
+
+        true
+    }
+
+    /// run a single testcase in a fresh child
+    pub fn run(&mut self, buf: &[u8]) -> Result<RunResult, Error> {
+        // respawn before writing the testcase, a new forkserver might not use shared memory
+        if !self.forkserver.is_qemu_alive() {
+            self.respawn_forkserver()?;
+        }
+
+        if !self.write_input(buf) {
+            return Ok(RunResult::Rejected);
+        }
+
+        self.spawn_child()?;
+
+        let result = match self.forkserver.read_status_timeout(self.timeout)? {
+            StatusRead::Status(raw_status) => {
+                let status = ChildStatus::from_raw(raw_status);
+                debug!("[+] child status {} ({})", raw_status, status);
+
+                if status.exit_kind() == ExitKind::Crash {
+                    info!("target crashed but QEMU is still alive. {}", status);
+                }
+
+                RunResult::Status(status)
+            }
+            StatusRead::Dead => {
+                info!("[!] target crashed");
+                RunResult::Dead
+            }
+            StatusRead::Timeout => {
+                // the forkserver reports the status of the killed child, drain it so the next
+                // execution reads its own status
+                self.forkserver.kill_child();
+                self.forkserver.try_read_status()?;
+                RunResult::Timeout
+            }
+        };
+
+        // rewind to start before new testcase
+        self.out_file.rewind();
+        Ok(result)
     }
 }
 
@@ -217,14 +781,19 @@ where
 {
     target: String,
     args: Vec<String>,
-    // use_stdin: bool,
-    out_file: OutFile,
-    forkserver: Forkserver,
+    use_stdin: bool,
+    runner: Rc<RefCell<ForkserverRunner>>,
     observers: OT,
+    /// directory to save hanging testcases into, if any
+    hangs_path: Option<PathBuf>,
+    hangs: u64,
+    /// QEMU respawns we already reported in the stats
+    reported_respawns: u64,
     phantom: PhantomData<(EM, I, S)>,
 }
 
-fn parse_argv(v: &Vec<String>, out_filename: &str) -> Vec<String> {
+/// replace every `@@` in the target arguments with the input file
+pub fn parse_argv(v: &Vec<String>, out_filename: &str) -> Vec<String> {
     let mut final_args = Vec::new();
     for item in v {
         if item == "@@" {
@@ -244,10 +813,15 @@ where
 {
     pub fn new<OC, OF, Z>(
         qemu: &str,
-        ld_library_path: &str,
+        qemu_env: QemuEnv,
         afl_persistent_addr: Option<String>,
+        persistent_hook: Option<String>,
         bin: &str,
         argv: Vec<String>,
+        out_file: OutFile,
+        use_stdin: bool,
+        use_shmem_input: bool,
+        timeout: Duration,
         observers: OT,
         _fuzzer: &mut Z,
         _state: &mut S,
@@ -260,31 +834,66 @@ where
         S: HasSolutions<OC, I>,
         Z: HasObjective<I, OF, S>,
     {
-        let target = bin.to_string();
-        let out_filename = format!("out-{}", 123456789); //TODO: replace it with a random number
-        let out_file = OutFile::new(&out_filename, 2048);
-        let args = parse_argv(&argv, &out_filename);
-
-        let mut forkserver = Forkserver::new(
-            qemu.to_string(),
-            ld_library_path.to_string(),
-            bin.to_string());
+        let runner = ForkserverRunner::new(
+            qemu,
+            qemu_env,
+            afl_persistent_addr,
+            persistent_hook,
+            bin,
+            argv,
+            out_file,
+            use_stdin,
+            use_shmem_input,
+            timeout,
+        )?;
 
-        if let Some(persistent_addr) = afl_persistent_addr {
-            forkserver.set_persistent_addr(persistent_addr);
-        }
+        Ok(Self::with_runner(Rc::new(RefCell::new(runner)), observers))
+    }
 
-        forkserver.start(args.clone());
-        forkserver.do_handshake();
+    /// create an executor around a runner that was started before, for example because a
+    /// feedback needs the runner before the executor can be created
+    pub fn with_runner(runner: Rc<RefCell<ForkserverRunner>>, observers: OT) -> Self {
+        let (target, args, use_stdin) = {
+            let r = runner.borrow();
+            (r.target.clone(), r.args.clone(), r.use_stdin)
+        };
 
-        return Ok(Self {
+        Self {
             target,
             args,
-            out_file,
-            forkserver,
+            use_stdin,
+            runner,
             observers,
+            hangs_path: None,
+            hangs: 0,
+            reported_respawns: 0,
             phantom: PhantomData,
-        });
+        }
+    }
+
+    /// what to do with testcases larger than the input file's max length
+    pub fn set_oversize_policy(&mut self, policy: OversizePolicy) {
+        self.runner.borrow_mut().set_oversize_policy(policy);
+    }
+
+    /// stop running testcases once a limit is reached, `run_target` then fails with
+    /// `Error::ShuttingDown`. without limits only signals stop the executor
+    pub fn set_limits(&mut self, limits: RunLimits) {
+        self.runner.borrow_mut().set_limits(limits);
+    }
+
+    /// why `run_target` (or a feedback sharing the runner) stopped running testcases, if it did
+    pub fn limit_reached(&self) -> Option<Limit> {
+        self.runner.borrow().limit_reached()
+    }
+
+    /// save testcases that hit the timeout into `path`
+    pub fn set_hangs_path(&mut self, path: PathBuf) {
+        self.hangs_path = Some(path);
+    }
+
+    pub fn hangs(&self) -> u64 {
+        self.hangs
     }
 
     pub fn target(&self) -> &String {
@@ -295,60 +904,132 @@ where
         &self.args
     }
 
-    pub fn forkserver(&self) -> &Forkserver {
-        &self.forkserver
+    pub fn use_stdin(&self) -> bool {
+        self.use_stdin
     }
 
-    fn mut_forkserver(&mut self) -> &mut Forkserver {
-        &mut self.forkserver
+    pub fn forkserver(&self) -> Ref<Forkserver> {
+        Ref::map(self.runner.borrow(), |runner| runner.forkserver())
+    }
+
+    pub fn mut_forkserver(&mut self) -> RefMut<Forkserver> {
+        RefMut::map(self.runner.borrow_mut(), |runner| runner.mut_forkserver())
+    }
+
+    /// a handle to the runner for whoever needs to run testcases outside of `run_target`
+    pub fn runner(&self) -> Rc<RefCell<ForkserverRunner>> {
+        self.runner.clone()
+    }
+
+    /// make the exit status of the last run available to feedbacks, if an `ExitStatusObserver`
+    /// is part of our observers
+    fn record_status(&mut self, status: Option<ChildStatus>) {
+        if let Some(observer) = self
+            .observers
+            .match_name_mut::<ExitStatusObserver>(EXIT_STATUS_ID)
+        {
+            observer.set_status(status);
+        }
+    }
+}
+
+impl<EM, I, OT, S> ForkserverExecutor<EM, I, OT, S>
+where
+    EM: EventFirer<I, S>,
+    I: Input + HasTargetBytes,
+    OT: ObserversTuple,
+{
+    /// report QEMU respawns that happened since the last report, also the ones caused by
+    /// other users of the runner
+    fn report_respawns(&mut self, state: &mut S, mgr: &mut EM) -> Result<(), Error> {
+        let respawns = self.forkserver().respawns();
+        if respawns == self.reported_respawns {
+            return Ok(());
+        }
+
+        self.reported_respawns = respawns;
+        mgr.fire(
+            state,
+            Event::UpdateUserStats {
+                name: RESPAWNS_STAT.to_string(),
+                value: UserStats::Number(respawns),
+                phantom: PhantomData,
+            },
+        )
+    }
+
+    /// count a testcase that hit the timeout and save it to the hangs directory
+    fn on_hang(&mut self, state: &mut S, mgr: &mut EM, input: &I) -> Result<(), Error> {
+        self.hangs += 1;
+        info!(
+            "[!] target timed out after {:?}. hangs={}",
+            self.runner.borrow().timeout(),
+            self.hangs
+        );
+
+        if let Some(hangs_path) = &self.hangs_path {
+            let path = hangs_path.join(input.generate_name(self.hangs as usize));
+            input.to_file(&path)?;
+            debug!("[+] saved hanging testcase to {:?}", path);
+        }
+
+        mgr.fire(
+            state,
+            Event::UpdateUserStats {
+                name: HANGS_STAT.to_string(),
+                value: UserStats::Number(self.hangs),
+                phantom: PhantomData,
+            },
+        )
     }
 }
 
 impl<EM, I, OT, S, Z> Executor<EM, I, S, Z> for ForkserverExecutor<EM, I, OT, S>
 where
+    EM: EventFirer<I, S>,
     I: Input + HasTargetBytes,
     OT: ObserversTuple,
+    S: HasExecutions,
 {
     #[inline]
     fn run_target(
         &mut self,
         _fuzzer: &mut Z,
-        _state: &mut S,
-        _mgr: &mut EM,
+        state: &mut S,
+        mgr: &mut EM,
         input: &I,
     ) -> Result<ExitKind, Error> {
+        // a stage runs many executions, so signals and run limits are checked before every
+        // one of them
+        let limit = self.runner.borrow_mut().check_limits(*state.executions());
+        if let Some(limit) = limit {
+            debug!("[!] not running the testcase, {}", limit);
+            return Err(Error::ShuttingDown);
+        }
 
-       // write new testcase to input file
-       self.out_file
-           .write_buf(&input.target_bytes().as_slice().to_vec());
-
-        let forkserver = self.mut_forkserver();
+        let result = self
+            .runner
+            .borrow_mut()
+            .run(input.target_bytes().as_slice())?;
 
-        forkserver.control_pipe.write_i32(0);
-        debug!("[+] sent alive signal to child");
+        self.report_respawns(state, mgr)?;
 
-        if let Some(child_pid) = forkserver.try_read_status() {
-            debug!("[+] child pid {}", child_pid);
-            forkserver.child_pid = child_pid;
-        } else {
-            panic!("forkserver is misbehaving");
-        }
-
-        if let Some(child_status) = forkserver.try_read_status() {
-            debug!("[+] child status {}", child_status);
-            if child_status != 4991 {
-                info!("target crashed but QEMU is still alive. exit_code={}", child_status);
-                return Ok(ExitKind::Crash);
+        match result {
+            RunResult::Status(status) => {
+                self.record_status(Some(status));
+                Ok(status.exit_kind())
             }
-        } else {
-            forkserver.is_qemu_alive = false;
-            info!("[!] target crashed");
-            return Ok(ExitKind::Crash);
+            RunResult::Dead => {
+                self.record_status(None);
+                Ok(ExitKind::Crash)
+            }
+            RunResult::Timeout => {
+                self.record_status(Some(ChildStatus::Signaled(libc::SIGKILL)));
+                self.on_hang(state, mgr, input)?;
+                Ok(ExitKind::Timeout)
+            }
+            RunResult::Rejected => Ok(ExitKind::Ok),
         }
-
-        // rewind to start before new testcase
-        self.out_file.rewind();
-        Ok(ExitKind::Ok)
     }
 }
 
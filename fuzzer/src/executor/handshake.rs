@@ -0,0 +1,180 @@
+use libafl::Error;
+
+use log::warn;
+
+// forkserver option flags, taken from AFLplusplus/include/types.h
+pub const FS_OPT_ENABLED: u32 = 0x80000001;
+pub const FS_OPT_MAPSIZE: u32 = 0x40000000;
+pub const FS_OPT_SNAPSHOT: u32 = 0x20000000;
+pub const FS_OPT_AUTODICT: u32 = 0x10000000;
+pub const FS_OPT_SHDMEM_FUZZ: u32 = 0x01000000;
+pub const FS_OPT_ERROR: u32 = 0xf800008f;
+
+const FS_ERROR_MAP_SIZE: u32 = 1;
+const FS_ERROR_MAP_ADDR: u32 = 2;
+const FS_ERROR_SHM_OPEN: u32 = 4;
+const FS_ERROR_SHMAT: u32 = 8;
+const FS_ERROR_MMAP: u32 = 16;
+const FS_ERROR_OLD_CMPLOG: u32 = 32;
+const FS_ERROR_OLD_CMPLOG_QEMU: u32 = 64;
+
+fn get_map_size(hello: u32) -> usize {
+    (((hello & 0x00fffffe) >> 1) + 1) as usize
+}
+
+fn get_error(hello: u32) -> u32 {
+    (hello & 0x00ffff00) >> 8
+}
+
+/// Options a forkserver announces in its hello message
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ForkserverOptions {
+    /// size of the coverage map the target writes to
+    pub map_size: Option<usize>,
+    /// the target wants to read testcases from shared memory
+    pub shmem_fuzz: bool,
+    /// the target has an automatic dictionary to send us
+    pub autodict: bool,
+    pub snapshot: bool,
+}
+
+impl ForkserverOptions {
+    /// parse the hello word. fails with a readable message if the forkserver reported an error
+    pub fn from_hello(hello: u32) -> Result<Self, Error> {
+        if hello & FS_OPT_ERROR == FS_OPT_ERROR {
+            return Err(Error::IllegalState(error_message(get_error(hello))));
+        }
+
+        // old forkservers send an arbitrary hello without any options
+        if hello & FS_OPT_ENABLED != FS_OPT_ENABLED {
+            return Ok(Self::default());
+        }
+
+        let map_size = if hello & FS_OPT_MAPSIZE == FS_OPT_MAPSIZE {
+            Some(get_map_size(hello))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            map_size,
+            shmem_fuzz: hello & FS_OPT_SHDMEM_FUZZ == FS_OPT_SHDMEM_FUZZ,
+            autodict: hello & FS_OPT_AUTODICT == FS_OPT_AUTODICT,
+            snapshot: hello & FS_OPT_SNAPSHOT == FS_OPT_SNAPSHOT,
+        })
+    }
+}
+
+fn error_message(error: u32) -> String {
+    let reason = match error {
+        FS_ERROR_MAP_SIZE => "the target requires a coverage map larger than the one it was given. \
+            Run it with AFL_DEBUG=1 and set AFL_MAP_SIZE to the value of __afl_final_loc",
+        FS_ERROR_MAP_ADDR => "the target was compiled with a fixed map address and mapping the \
+            shared memory there failed",
+        FS_ERROR_SHM_OPEN => "the target could not open the shared memory (shm_open failed)",
+        FS_ERROR_SHMAT => "the target could not attach the shared memory (shmat failed)",
+        FS_ERROR_MMAP => "the target could not map the shared memory (mmap failed)",
+        FS_ERROR_OLD_CMPLOG => "the cmplog target was instrumented with an old AFL++ version",
+        FS_ERROR_OLD_CMPLOG_QEMU => "the cmplog QEMU is too old, rebuild qemu_mode",
+        _ => "unknown error",
+    };
+
+    format!("forkserver reported error {:#x}: {}", error, reason)
+}
+
+// autodict size limits, taken from AFLplusplus/src/afl-forkserver.c
+const AUTODICT_MIN_LEN: u32 = 2;
+const AUTODICT_MAX_LEN: u32 = 0xffffff;
+
+/// check the autodict size sent by the forkserver before reading that many bytes
+pub fn autodict_len(len: u32) -> Result<usize, Error> {
+    if !(AUTODICT_MIN_LEN..=AUTODICT_MAX_LEN).contains(&len) {
+        return Err(Error::IllegalState(format!(
+            "forkserver sent an autodict of {} bytes, expected {} to {}",
+            len, AUTODICT_MIN_LEN, AUTODICT_MAX_LEN
+        )));
+    }
+
+    Ok(len as usize)
+}
+
+/// the autodict is a sequence of tokens, each one prefixed with its length as a single byte
+pub fn parse_autodict(data: &[u8]) -> Vec<Vec<u8>> {
+    let mut tokens = Vec::new();
+    let mut offset = 0;
+
+    while offset < data.len() {
+        let len = data[offset] as usize;
+        offset += 1;
+
+        if offset + len > data.len() {
+            warn!("autodict is truncated, ignoring its last token");
+            break;
+        }
+
+        tokens.push(data[offset..offset + len].to_vec());
+        offset += len;
+    }
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_forkserver_has_no_options() {
+        assert_eq!(
+            ForkserverOptions::from_hello(0).unwrap(),
+            ForkserverOptions::default()
+        );
+    }
+
+    #[test]
+    fn parses_options() {
+        // FS_OPT_SET_MAPSIZE(65536)
+        let map_size = (65536 - 1) << 1;
+        let hello =
+            FS_OPT_ENABLED | FS_OPT_MAPSIZE | FS_OPT_SHDMEM_FUZZ | FS_OPT_AUTODICT | map_size;
+
+        let options = ForkserverOptions::from_hello(hello).unwrap();
+        assert_eq!(options.map_size, Some(65536));
+        assert!(options.shmem_fuzz);
+        assert!(options.autodict);
+        assert!(!options.snapshot);
+    }
+
+    #[test]
+    fn reports_errors() {
+        let hello = FS_OPT_ERROR | (FS_ERROR_SHMAT << 8);
+        match ForkserverOptions::from_hello(hello) {
+            Err(Error::IllegalState(msg)) => assert!(msg.contains("shmat")),
+            other => panic!("expected an error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_bad_autodict_lengths() {
+        assert!(autodict_len(0).is_err());
+        assert!(autodict_len(1).is_err());
+        assert!(autodict_len(0x1000000).is_err());
+        assert!(autodict_len(u32::MAX).is_err());
+        assert_eq!(autodict_len(2).unwrap(), 2);
+        assert_eq!(autodict_len(0xffffff).unwrap(), 0xffffff);
+    }
+
+    #[test]
+    fn parses_autodict() {
+        let data = b"\x03foo\x00\x02ab";
+        assert_eq!(
+            parse_autodict(data),
+            vec![b"foo".to_vec(), Vec::new(), b"ab".to_vec()]
+        );
+    }
+
+    #[test]
+    fn drops_truncated_autodict_token() {
+        assert_eq!(parse_autodict(b"\x01a\x05abc"), vec![b"a".to_vec()]);
+    }
+}
@@ -1,2 +1,6 @@
 pub mod forkserver;
+pub mod handshake;
+pub mod qemu_env;
 pub mod simple;
+pub mod status;
+pub mod triage;
@@ -0,0 +1,114 @@
+use std::{ops::Range, process::Command};
+
+use crate::inst_ranges::format_ranges;
+
+/// Environment QEMU is started with. every executor (forkserver, crash replay, showmap) applies
+/// the same one, so the target runs and is instrumented the same way everywhere
+#[derive(Debug, Clone, Default)]
+pub struct QemuEnv {
+    /// variables of the QEMU process itself
+    qemu_vars: Vec<(String, String)>,
+    /// variables QEMU sets for the guest, passed in QEMU_SET_ENV
+    guest_vars: Vec<(String, String)>,
+    /// instrument shared libraries too, not only the target binary
+    inst_libs: bool,
+    /// only instrument these address ranges, `start-end` pairs separated by commas
+    inst_ranges: Option<String>,
+    /// don't instrument these address ranges
+    exclude_ranges: Option<String>,
+    /// directory QEMU loads the guest's interpreter and libraries from
+    ld_prefix: Option<String>,
+}
+
+impl QemuEnv {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_qemu_var(&mut self, name: &str, value: &str) {
+        self.qemu_vars.push((name.to_string(), value.to_string()));
+    }
+
+    /// QEMU splits QEMU_SET_ENV on commas, so the value can't contain one
+    pub fn add_guest_var(&mut self, name: &str, value: &str) {
+        self.guest_vars.push((name.to_string(), value.to_string()));
+    }
+
+    pub fn set_inst_libs(&mut self, inst_libs: bool) {
+        self.inst_libs = inst_libs;
+    }
+
+    pub fn set_inst_ranges(&mut self, ranges: Option<String>) {
+        self.inst_ranges = ranges;
+    }
+
+    pub fn set_exclude_ranges(&mut self, ranges: Option<String>) {
+        self.exclude_ranges = ranges;
+    }
+
+    /// instrument `ranges` in addition to the ones already set
+    pub fn add_inst_ranges(&mut self, ranges: &[Range<u64>]) {
+        self.inst_ranges = join_ranges(self.inst_ranges.take(), ranges);
+    }
+
+    /// leave `ranges` alone in addition to the ones already excluded
+    pub fn add_exclude_ranges(&mut self, ranges: &[Range<u64>]) {
+        self.exclude_ranges = join_ranges(self.exclude_ranges.take(), ranges);
+    }
+
+    pub fn set_ld_prefix(&mut self, ld_prefix: Option<String>) {
+        self.ld_prefix = ld_prefix;
+    }
+
+    /// every variable set on QEMU, in order. the extra QEMU variables come last so they can
+    /// override anything we set
+    pub fn vars(&self) -> Vec<(String, String)> {
+        let mut vars = Vec::new();
+
+        if self.inst_libs {
+            vars.push(("AFL_INST_LIBS".to_string(), "1".to_string()));
+        }
+
+        if let Some(ranges) = &self.inst_ranges {
+            vars.push(("AFL_QEMU_INST_RANGES".to_string(), ranges.clone()));
+        }
+
+        if let Some(ranges) = &self.exclude_ranges {
+            vars.push(("AFL_QEMU_EXCLUDE_RANGES".to_string(), ranges.clone()));
+        }
+
+        if let Some(ld_prefix) = &self.ld_prefix {
+            vars.push(("QEMU_LD_PREFIX".to_string(), ld_prefix.clone()));
+        }
+
+        if !self.guest_vars.is_empty() {
+            let guest_env: Vec<String> = self
+                .guest_vars
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect();
+            vars.push(("QEMU_SET_ENV".to_string(), guest_env.join(",")));
+        }
+
+        vars.extend(self.qemu_vars.iter().cloned());
+        vars
+    }
+
+    pub fn apply(&self, cmd: &mut Command) {
+        for (name, value) in self.vars() {
+            cmd.env(name, value);
+        }
+    }
+}
+
+fn join_ranges(ranges: Option<String>, more: &[Range<u64>]) -> Option<String> {
+    if more.is_empty() {
+        return ranges;
+    }
+
+    let more = format_ranges(more);
+    match ranges {
+        Some(ranges) if !ranges.is_empty() => Some(format!("{},{}", ranges, more)),
+        _ => Some(more),
+    }
+}
@@ -8,18 +8,20 @@ use libafl::executors::ExitKind;
 
 use log::{debug, log_enabled, Level};
 
+use super::{qemu_env::QemuEnv, status::ChildStatus};
+
 pub struct SimpleQEMU {
     qemu_path: String,
-    ld_library_path: Option<String>,
+    qemu_env: QemuEnv,
     // target: String,
     // args: Vec<String>,
 }
 
 impl SimpleQEMU {
-    pub fn new(qemu_path: String, ld_library_path: Option<String>) -> Self {
+    pub fn new(qemu_path: String, qemu_env: QemuEnv) -> Self {
         Self {
             qemu_path,
-            ld_library_path,
+            qemu_env,
         }
     }
 
@@ -39,12 +41,8 @@ impl SimpleQEMU {
             .args(args.clone())
             .stdin(Stdio::null())
             .stdout(stdout)
-            .stderr(stderr)
-            .env("AFL_INST_LIBS", "1");
-
-        if let Some(ld_library) = &self.ld_library_path {
-            cmd.env("QEMU_SET_ENV",&format!("LD_LIBRARY_PATH={}", ld_library));
-        }
+            .stderr(stderr);
+        self.qemu_env.apply(&mut cmd);
 
         if log_enabled!(Level::Debug) {
             // cmd.env("AFL_QEMU_DEBUG_MAPS", "1");
@@ -58,12 +56,8 @@ impl SimpleQEMU {
             return ExitKind::Ok;
         }
 
-        #[cfg(target_family="unix")]
-        if let Some(signal) = output.status.signal() {
-            debug!("QEMU signal exit {}", signal);
-            return ExitKind::Crash;
-        }
-
-        ExitKind::Ok
+        let status = ChildStatus::from_raw(output.status.into_raw());
+        debug!("QEMU exit {}", status);
+        status.exit_kind()
     }
 }
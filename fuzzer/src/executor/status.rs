@@ -0,0 +1,113 @@
+use std::fmt;
+
+use libafl::executors::ExitKind;
+use serde::{Deserialize, Serialize};
+
+/// wait status of the fuzzed program as reported by the forkserver, decoded like `waitpid`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChildStatus {
+    /// the target called exit with the given code
+    Exited(i32),
+    /// the target was terminated by a signal
+    Signaled(i32),
+    /// the target was stopped by a signal. in persistent mode QEMU stops the target with SIGSTOP
+    /// at the end of every iteration (raw status 4991)
+    Stopped(i32),
+}
+
+impl ChildStatus {
+    pub fn from_raw(status: i32) -> Self {
+        if libc::WIFSIGNALED(status) {
+            ChildStatus::Signaled(libc::WTERMSIG(status))
+        } else if libc::WIFSTOPPED(status) {
+            ChildStatus::Stopped(libc::WSTOPSIG(status))
+        } else {
+            ChildStatus::Exited(libc::WEXITSTATUS(status))
+        }
+    }
+
+    /// signal that terminated the target, if any
+    pub fn signal(&self) -> Option<i32> {
+        match self {
+            ChildStatus::Signaled(sig) => Some(*sig),
+            _ => None,
+        }
+    }
+
+    /// exit code of the target, if it exited normally
+    pub fn exit_code(&self) -> Option<i32> {
+        match self {
+            ChildStatus::Exited(code) => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// a normal exit is not a crash, no matter the exit code. only death by signal is
+    pub fn exit_kind(&self) -> ExitKind {
+        match self {
+            ChildStatus::Signaled(_) => ExitKind::Crash,
+            ChildStatus::Exited(_) | ChildStatus::Stopped(_) => ExitKind::Ok,
+        }
+    }
+}
+
+impl fmt::Display for ChildStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChildStatus::Exited(code) => write!(f, "exited with code {}", code),
+            ChildStatus::Signaled(sig) => write!(f, "killed by {} ({})", signal_name(*sig), sig),
+            ChildStatus::Stopped(sig) => write!(f, "stopped by {} ({})", signal_name(*sig), sig),
+        }
+    }
+}
+
+pub fn signal_name(sig: i32) -> &'static str {
+    match sig {
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGILL => "SIGILL",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGTRAP => "SIGTRAP",
+        libc::SIGSYS => "SIGSYS",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGINT => "SIGINT",
+        libc::SIGSTOP => "SIGSTOP",
+        _ => "unknown signal",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_exit() {
+        assert_eq!(ChildStatus::from_raw(0), ChildStatus::Exited(0));
+        assert_eq!(ChildStatus::from_raw(3 << 8), ChildStatus::Exited(3));
+        assert_eq!(ChildStatus::from_raw(3 << 8).exit_kind(), ExitKind::Ok);
+    }
+
+    #[test]
+    fn decodes_signal() {
+        let status = ChildStatus::from_raw(libc::SIGSEGV);
+        assert_eq!(status, ChildStatus::Signaled(libc::SIGSEGV));
+        assert_eq!(status.signal(), Some(libc::SIGSEGV));
+        assert_eq!(status.exit_kind(), ExitKind::Crash);
+
+        // core dumped
+        assert_eq!(
+            ChildStatus::from_raw(libc::SIGABRT | 0x80),
+            ChildStatus::Signaled(libc::SIGABRT)
+        );
+    }
+
+    #[test]
+    fn decodes_persistent_stop() {
+        let status = ChildStatus::from_raw(4991);
+        assert_eq!(status, ChildStatus::Stopped(libc::SIGSTOP));
+        assert_eq!(status.exit_kind(), ExitKind::Ok);
+        assert_eq!(status.signal(), None);
+    }
+}
@@ -0,0 +1,495 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fs::{self, File},
+    hash::Hasher,
+    io::{prelude::*, BufReader},
+    os::unix::process::CommandExt,
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use libafl::Error;
+use log::{debug, warn};
+
+use super::{
+    forkserver::{parse_argv, FORKSRV_FD},
+    qemu_env::QemuEnv,
+};
+use crate::{outfile::OutFile, shmem_input::SHM_FUZZ_ENV_VAR};
+
+const WAIT_INTERVAL: Duration = Duration::from_millis(10);
+/// frames that go into the stack hash, the faulting block and its closest callers. fixed so
+/// the hash doesn't change with `triage_depth` and suppressions keep matching
+const HASH_FRAMES: usize = 4;
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Where a crash happened: the faulting block followed by the blocks that made the calls
+/// leading to it, innermost first
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backtrace {
+    /// at most `triage_depth` frames
+    pub pcs: Vec<u64>,
+    hash: u64,
+}
+
+impl Backtrace {
+    /// `frames` is the whole stack, innermost first. pcs are hashed relative to `load_base`
+    /// so the hash is the same for every run of the target
+    pub fn new(frames: &[u64], depth: usize, load_base: u64) -> Self {
+        let mut hash = FNV_OFFSET;
+        for pc in frames.iter().take(HASH_FRAMES) {
+            for byte in pc.wrapping_sub(load_base).to_le_bytes().iter() {
+                hash ^= *byte as u64;
+                hash = hash.wrapping_mul(FNV_PRIME);
+            }
+        }
+
+        Self {
+            pcs: frames.iter().take(depth.max(1)).cloned().collect(),
+            hash,
+        }
+    }
+
+    pub fn faulting_pc(&self) -> Option<u64> {
+        self.pcs.first().cloned()
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+}
+
+/// Replays a crashing testcase under plain afl-qemu-trace (no forkserver) with the exec log
+/// turned on, to find out where the target crashed
+pub struct CrashTriage {
+    qemu_path: String,
+    qemu_env: QemuEnv,
+    target: String,
+    args: Vec<String>,
+    use_stdin: bool,
+    /// number of frames kept in a backtrace
+    depth: usize,
+    /// where QEMU maps the target, see `elf::load_base`
+    load_base: u64,
+    timeout: Duration,
+    input_file: OutFile,
+    log_path: PathBuf,
+    /// hash of the last replayed testcase and its backtrace. the queue and the objective may
+    /// both ask about the same crash
+    last: Option<(u64, Option<Backtrace>)>,
+}
+
+impl CrashTriage {
+    pub fn new(
+        qemu_path: String,
+        qemu_env: QemuEnv,
+        target: String,
+        argv: Vec<String>,
+        use_stdin: bool,
+        input_dir: Option<&Path>,
+        max_len: u64,
+    ) -> Self {
+        let input_file = OutFile::new_unique_with_prefix(input_dir, ".triage_input", max_len);
+        let input_path = input_file.path().to_str().expect("Invalid input file path");
+        let args = parse_argv(&argv, input_path);
+
+        let log_name = format!(".triage_log-{}", process::id());
+        let log_path = match input_dir {
+            Some(dir) => dir.join(log_name),
+            None => PathBuf::from(log_name),
+        };
+
+        Self {
+            qemu_path,
+            qemu_env,
+            target,
+            args,
+            use_stdin,
+            depth: 5,
+            load_base: 0,
+            timeout: Duration::from_secs(10),
+            input_file,
+            log_path,
+            last: None,
+        }
+    }
+
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+    }
+
+    pub fn set_load_base(&mut self, load_base: u64) {
+        self.load_base = load_base;
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// run the testcase once and read the backtrace from the exec log. None if the log had
+    /// no executed blocks in it. asking again about the last testcase does not replay it
+    pub fn replay(&mut self, buf: &Vec<u8>) -> Result<Option<Backtrace>, Error> {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(buf);
+        let input_hash = hasher.finish();
+
+        if let Some((hash, backtrace)) = &self.last {
+            if *hash == input_hash {
+                return Ok(backtrace.clone());
+            }
+        }
+
+        let backtrace = self.run(buf)?;
+        self.last = Some((input_hash, backtrace.clone()));
+        Ok(backtrace)
+    }
+
+    fn run(&mut self, buf: &Vec<u8>) -> Result<Option<Backtrace>, Error> {
+        self.input_file.write_buf(buf);
+        self.input_file.rewind();
+
+        let mut cmd = Command::new(&self.qemu_path);
+        // in_asm tells us which blocks end in a call or a return
+        cmd.arg("-d")
+            .arg("exec,nochain,in_asm")
+            .arg("-D")
+            .arg(&self.log_path)
+            .arg(&self.target)
+            .args(&self.args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            // don't let the replay write into the fuzzer's coverage map, and make it read the
+            // testcase from our input file rather than the fuzzer's shared memory
+            .env_remove("__AFL_SHM_ID")
+            .env_remove(SHM_FUZZ_ENV_VAR);
+        self.qemu_env.apply(&mut cmd);
+
+        if self.use_stdin {
+            cmd.stdin(Stdio::from(self.input_file.try_clone_file()?));
+        } else {
+            cmd.stdin(Stdio::null());
+        }
+
+        // like the forkserver, keep the replay out of the terminal's process group so a Ctrl-C
+        // lets us finish it instead of killing it under us
+        unsafe {
+            cmd.pre_exec(|| {
+                // the forkserver pipes are inherited, a QEMU that finds them open starts a
+                // forkserver of its own on them and talks to the fuzzer in the real one's place
+                libc::close(FORKSRV_FD);
+                libc::close(FORKSRV_FD + 1);
+
+                if libc::setpgid(0, 0) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        let mut child = cmd.spawn()?;
+        let start = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break Some(status);
+            }
+
+            if start.elapsed() > self.timeout {
+                warn!("[!] crash replay timed out, using the log so far");
+                child.kill()?;
+                child.wait()?;
+                break None;
+            }
+
+            thread::sleep(WAIT_INTERVAL);
+        };
+
+        debug!("crash replay exited with {:?}", status);
+
+        let backtrace = self.read_backtrace()?;
+        debug!("crash replay backtrace {:x?}", backtrace);
+        Ok(backtrace)
+    }
+
+    fn read_backtrace(&self) -> Result<Option<Backtrace>, Error> {
+        let log = BufReader::new(File::open(&self.log_path)?);
+        let mut stack = ShadowStack::default();
+        for line in log.split(b'\n') {
+            stack.feed(&String::from_utf8_lossy(&line?));
+        }
+
+        let frames = stack.frames();
+        if frames.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(Backtrace::new(&frames, self.depth, self.load_base)))
+    }
+}
+
+impl Drop for CrashTriage {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.log_path);
+    }
+}
+
+/// guest pc of a `-d exec` line, which looks like
+/// `Trace 0: 0x7f1c4c000100 [0000000000000000/0000005500001234/0x80000000] main`
+fn parse_exec_line(line: &str) -> Option<u64> {
+    if !line.starts_with("Trace ") {
+        return None;
+    }
+
+    let start = line.find('[')?;
+    let end = line[start..].find(']')? + start;
+    let pc = line[start + 1..end].split('/').nth(1)?;
+
+    u64::from_str_radix(pc, 16).ok()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BlockEnd {
+    Call,
+    Return,
+    Other,
+}
+
+/// Follows the calls and returns of the target through the exec log and keeps the blocks that
+/// made the calls still on the stack when it stopped
+#[derive(Debug, Default)]
+struct ShadowStack {
+    /// how every translated block ends, from the in_asm part of the log
+    blocks: HashMap<u64, BlockEnd>,
+    /// start and last instruction of the block being read from in_asm
+    translating: Option<(u64, BlockEnd)>,
+    /// blocks that made a call, outermost first
+    callers: Vec<u64>,
+    /// last block executed
+    last: Option<u64>,
+}
+
+impl ShadowStack {
+    fn feed(&mut self, line: &str) {
+        if let Some(insn) = parse_insn_line(line) {
+            let start = self.translating.map_or(insn.0, |(start, _)| start);
+            self.translating = Some((start, insn.1));
+            return;
+        }
+
+        // anything else ends the block
+        if let Some((start, end)) = self.translating.take() {
+            self.blocks.insert(start, end);
+        }
+
+        if let Some(pc) = parse_exec_line(line) {
+            // the previous block ran to its end since another one ran after it
+            if let Some(last) = self.last {
+                match self.blocks.get(&last) {
+                    Some(BlockEnd::Call) => self.callers.push(last),
+                    Some(BlockEnd::Return) => {
+                        self.callers.pop();
+                    }
+                    _ => (),
+                }
+            }
+
+            self.last = Some(pc);
+        }
+    }
+
+    /// faulting block followed by the callers, innermost first
+    fn frames(&self) -> Vec<u64> {
+        self.last
+            .iter()
+            .chain(self.callers.iter().rev())
+            .cloned()
+            .collect()
+    }
+}
+
+/// address and kind of an in_asm instruction line, which looks like
+/// `0x0000005500001234:  94000010  bl       #0x5500001270`
+fn parse_insn_line(line: &str) -> Option<(u64, BlockEnd)> {
+    let idx = line.find(':')?;
+    let addr = u64::from_str_radix(line[..idx].strip_prefix("0x")?, 16).ok()?;
+
+    // the instruction bytes come before the mnemonic
+    let mnemonic = line[idx + 1..]
+        .split_whitespace()
+        .find(|word| word.len() % 2 == 1 || !word.chars().all(|c| c.is_ascii_hexdigit()))?;
+
+    let end = match mnemonic {
+        "bl" | "blr" | "blraa" | "blraaz" | "blrab" | "blrabz" | "call" | "callq" => BlockEnd::Call,
+        m if m.starts_with("ret") => BlockEnd::Return,
+        _ => BlockEnd::Other,
+    };
+
+    Some((addr, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipe::Pipe;
+    use std::{env, os::unix::fs::PermissionsExt};
+
+    const BASE: u64 = 0x5500000000;
+
+    /// in_asm of a block followed by its execution
+    fn block(stack: &mut ShadowStack, pc: u64, insns: &[&str]) {
+        stack.feed("----------------");
+        stack.feed("IN: func");
+        for (i, insn) in insns.iter().enumerate() {
+            stack.feed(&format!(
+                "0x{:016x}:  d503201f  {}",
+                pc + 4 * i as u64,
+                insn
+            ));
+        }
+        stack.feed("");
+        run(stack, pc);
+    }
+
+    fn run(stack: &mut ShadowStack, pc: u64) {
+        stack.feed(&format!(
+            "Trace 0: 0x7f1c4c000100 [0000000000000000/{:016x}/0x80000000] func",
+            pc
+        ));
+    }
+
+    #[test]
+    fn parses_exec_lines() {
+        assert_eq!(
+            parse_exec_line(
+                "Trace 0: 0x7f1c4c000100 [0000000000000000/0000005500001234/0x80000000] main"
+            ),
+            Some(0x5500001234)
+        );
+        assert_eq!(parse_exec_line("IN: main"), None);
+        assert_eq!(parse_exec_line("Trace 0: 0x7f1c4c000100 [broken"), None);
+    }
+
+    #[test]
+    fn parses_insn_lines() {
+        assert_eq!(
+            parse_insn_line("0x0000005500001234:  94000010  bl       #0x5500001274"),
+            Some((0x5500001234, BlockEnd::Call))
+        );
+        assert_eq!(
+            parse_insn_line("0x0000005500001238:  d65f03c0  ret      "),
+            Some((0x5500001238, BlockEnd::Return))
+        );
+        assert_eq!(
+            parse_insn_line("0x000000550000123c:  8b020020  add      x0, x1, x2"),
+            Some((0x550000123c, BlockEnd::Other))
+        );
+        assert_eq!(
+            parse_insn_line("Trace 0: 0x7f1c4c000100 [0/5500001234/0] main"),
+            None
+        );
+    }
+
+    #[test]
+    fn follows_calls_and_returns() {
+        let mut stack = ShadowStack::default();
+        // main calls a, a calls b and returns, then main calls c which crashes
+        block(&mut stack, BASE + 0x100, &["mov x0, x1", "bl #0x200"]);
+        block(&mut stack, BASE + 0x200, &["bl #0x300"]);
+        block(&mut stack, BASE + 0x300, &["ret"]);
+        block(&mut stack, BASE + 0x204, &["ret"]);
+        block(&mut stack, BASE + 0x108, &["bl #0x400"]);
+        block(&mut stack, BASE + 0x400, &["ldr x0, [x1]"]);
+
+        assert_eq!(stack.frames(), vec![BASE + 0x400, BASE + 0x108]);
+    }
+
+    #[test]
+    fn a_crash_in_a_call_block_is_not_its_own_caller() {
+        let mut stack = ShadowStack::default();
+        block(&mut stack, BASE + 0x100, &["bl #0x200"]);
+        block(&mut stack, BASE + 0x200, &["ldr x0, [x1]", "bl #0x300"]);
+
+        assert_eq!(stack.frames(), vec![BASE + 0x200, BASE + 0x100]);
+    }
+
+    #[test]
+    fn loops_do_not_grow_the_stack() {
+        let mut stack = ShadowStack::default();
+        block(&mut stack, BASE + 0x100, &["bl #0x200"]);
+        block(&mut stack, BASE + 0x200, &["cbnz x0, #0x200"]);
+        run(&mut stack, BASE + 0x200);
+        run(&mut stack, BASE + 0x200);
+
+        assert_eq!(stack.frames(), vec![BASE + 0x200, BASE + 0x100]);
+    }
+
+    #[test]
+    fn replay_does_not_see_the_forkserver() {
+        let dir = env::temp_dir().join(format!("triage-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        // stands in for afl-qemu-trace: -d <flags> -D <log> <target> <args>. it only logs a
+        // block if none of the fuzzer's forkserver state leaked into it
+        let qemu_path = dir.join("fake-qemu");
+        fs::write(
+            &qemu_path,
+            "#!/bin/sh\n\
+             if [ -e /proc/$$/fd/198 ] || [ -e /proc/$$/fd/199 ] || [ -n \"$__AFL_SHM_FUZZ_ID\" ]; then\n\
+                 exit 1\n\
+             fi\n\
+             echo 'Trace 0: 0x7f1c4c000100 [0000000000000000/0000005500001234/0x80000000] main' > \"$4\"\n",
+        )
+        .unwrap();
+        fs::set_permissions(&qemu_path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        // the pipes and the testcase shared memory of a running forkserver
+        let mut control_pipe = Pipe::new("control_pipe".to_owned()).unwrap();
+        let mut status_pipe = Pipe::new("status_pipe".to_owned()).unwrap();
+        control_pipe.dup_read(FORKSRV_FD).unwrap();
+        status_pipe.dup_write(FORKSRV_FD + 1).unwrap();
+        env::set_var(SHM_FUZZ_ENV_VAR, "1234");
+
+        let mut triage = CrashTriage::new(
+            qemu_path.to_string_lossy().to_string(),
+            QemuEnv::new(),
+            "target".to_string(),
+            vec!["@@".to_string()],
+            false,
+            Some(&dir),
+            1024,
+        );
+        let backtrace = triage.replay(&b"crash".to_vec());
+
+        env::remove_var(SHM_FUZZ_ENV_VAR);
+        drop(triage);
+        let _ = fs::remove_dir_all(&dir);
+
+        let backtrace = backtrace.unwrap().expect("the replay saw the forkserver");
+        assert_eq!(backtrace.faulting_pc(), Some(0x5500001234));
+        // nothing was sent to the fuzzer in the forkserver's place
+        assert!(!status_pipe.poll_read(Duration::from_millis(0)).unwrap());
+    }
+
+    #[test]
+    fn hash_does_not_depend_on_depth() {
+        let frames = [BASE + 0x400, BASE + 0x108, BASE + 0x20, BASE + 0x10];
+        let short = Backtrace::new(&frames, 1, BASE);
+        let long = Backtrace::new(&frames, 10, BASE);
+
+        assert_eq!(short.pcs, vec![BASE + 0x400]);
+        assert_eq!(long.pcs, frames.to_vec());
+        assert_eq!(short.hash(), long.hash());
+    }
+
+    #[test]
+    fn hash_is_relative_to_the_load_base() {
+        let pie = Backtrace::new(&[BASE + 0x400, BASE + 0x108], 5, BASE);
+        let moved = Backtrace::new(&[0x400, 0x108], 5, 0);
+        let other = Backtrace::new(&[0x400, 0x10c], 5, 0);
+
+        assert_eq!(pie.hash(), moved.hash());
+        assert_ne!(pie.hash(), other.hash());
+    }
+}
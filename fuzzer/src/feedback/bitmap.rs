@@ -11,15 +11,25 @@ use libafl::{
     stats::UserStats,
     Error,
 };
+use serde::{Deserialize, Serialize};
 use std::{collections::hash_map::DefaultHasher, hash::Hasher};
 
-use crate::observer::SharedMemObserver;
+use crate::{coverage::CoverageMode, observer::SharedMemObserver};
 
 use super::bitmap_state::CoverageFeedbackState;
 
 pub type MaxBitmapFeedback<FT, S> = BitmapFeedback<FT, MaxReducer, S>;
 
-use log::{debug, trace};
+/// Whether a testcase reached an edge never seen before, as opposed to only new hit counts
+/// of known edges. AFL marks the former with `+cov` in the queue file name
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NewCoverageMetadata {
+    pub new_edges: bool,
+}
+
+libafl::impl_serdeany!(NewCoverageMetadata);
+
+use log::debug;
 
 pub struct BitmapFeedback<FT, R, S>
 where
@@ -33,6 +43,8 @@ where
     observer_name: String,
     /// Name identifier of the shared feedback state
     feedback_state_name: String,
+    /// how map bytes are turned into coverage ids
+    mode: CoverageMode,
 
     // vector containing all the basic-block identifiers that we hit in this target run
     current_coverage: Vec<usize>,
@@ -52,6 +64,7 @@ where
             name: name.to_string(),
             observer_name: name.to_string(),
             feedback_state_name: name.to_string(),
+            mode: CoverageMode::Bits,
             current_coverage: Vec::new(),
             current_path_hash: 0,
             max_coverage_stat: 0,
@@ -64,6 +77,7 @@ where
             name: name.to_string(),
             observer_name: observer_name.to_string(),
             feedback_state_name: feedback_state_name.to_string(),
+            mode: CoverageMode::Bits,
             current_coverage: Vec::new(),
             current_path_hash: 0,
             max_coverage_stat: 0,
@@ -71,26 +85,16 @@ where
         }
     }
 
+    /// choose how map bytes are interpreted, the feedback state must be sized for this mode
+    pub fn set_mode(&mut self, mode: CoverageMode) {
+        self.mode = mode;
+    }
+
     /// check if the map in the given index has any coverage information
     /// if so add it to `self.current_coverage`
     fn visit_coverage_byte(&mut self, map: &[u8], byte_index: usize) {
-        let item = map[byte_index];
-        // no coverage for this index
-        if item == 0 {
-            return;
-        }
-
-        // we found coverage in this index. figure out which bit is turned on
-        for bit_index in 0..8 as u8 {
-            let mask: u8 = 1 << bit_index;
-            let empty = (item & mask) == 0;
-            let basic_block_id = byte_index * 8 + bit_index as usize;
-
-            if !empty {
-                trace!("push coverage id {}", basic_block_id);
-                self.current_coverage.push(basic_block_id);
-            }
-        }
+        self.mode
+            .visit_byte(byte_index, map[byte_index], &mut self.current_coverage);
     }
 
     /// hash the path stored in `self.current_coverage` and store it in `self.current_path_hash`
@@ -167,7 +171,9 @@ where
             .match_name_mut::<CoverageFeedbackState>(&self.feedback_state_name.to_string())
             .unwrap();
 
+        let new_edges = map_state.has_new_edge(&self.current_coverage, self.mode);
         map_state.mark_path(self.current_coverage.as_slice())?;
+        testcase.add_metadata(NewCoverageMetadata { new_edges });
 
         let meta = MapIndexesMetadata::new(core::mem::take(&mut self.current_coverage));
         testcase.add_metadata(meta);
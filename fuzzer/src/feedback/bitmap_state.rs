@@ -7,6 +7,8 @@ use libafl::{
 };
 
 use serde::{Deserialize, Serialize};
+
+use crate::coverage::CoverageMode;
 use log::debug;
 
 /// Holds all coverage ever seen
@@ -63,6 +65,16 @@ impl CoverageFeedbackState {
         Ok(false)
     }
 
+    /// true if the path has an id none of whose `edge_ids` was ever seen, meaning it reached
+    /// a new edge and not just a new hit count of a known one
+    pub fn has_new_edge(&self, path: &[usize], mode: CoverageMode) -> bool {
+        path.iter().any(|id| {
+            !mode
+                .edge_ids(*id)
+                .any(|i| self.all_time_coverage.get(i).cloned().unwrap_or(false))
+        })
+    }
+
     /// returns true if increased all-time count, false if no change
     pub fn mark_path(&mut self, path: &[usize]) -> Result<(), Error> {
         for edge in path {
@@ -77,6 +89,18 @@ impl CoverageFeedbackState {
         Ok(())
     }
 
+    /// change the number of coverage ids this state can hold, used once the target reported
+    /// its real map size
+    pub fn resize(&mut self, map_size: usize) {
+        debug!("resizing coverage state {} to {}", self.name, map_size);
+        self.all_time_coverage.resize(map_size, false);
+    }
+
+    /// number of coverage ids this state holds
+    pub fn map_size(&self) -> usize {
+        self.all_time_coverage.len()
+    }
+
     pub fn get_all_time_count(&self) -> u64 {
         self.count
     }
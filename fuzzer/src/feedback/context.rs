@@ -0,0 +1,99 @@
+use core::marker::PhantomData;
+use libafl::{
+    bolts::tuples::Named,
+    corpus::{Corpus, Testcase},
+    events::EventFirer,
+    executors::ExitKind,
+    feedbacks::Feedback,
+    inputs::Input,
+    observers::{ObserversTuple, TimeObserver},
+    state::{HasCorpus, HasMetadata},
+    Error,
+};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::queue::MUTATION_OP;
+
+/// Where a crashing testcase came from
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CrashContextMetadata {
+    /// queue entry that was mutated into the crash, None while loading the initial corpus
+    pub parent: Option<usize>,
+    /// stage that produced the crash
+    pub stage: String,
+    /// how long the crashing run took
+    pub exec_time: Option<Duration>,
+}
+
+libafl::impl_serdeany!(CrashContextMetadata);
+
+/// Never decides anything, it only records the parent, stage and run time of a testcase.
+/// put it in the objective so every crash carries its origin
+pub struct CrashContextFeedback<C> {
+    time_observer_name: String,
+    exec_time: Option<Duration>,
+    phantom: PhantomData<C>,
+}
+
+impl<C> CrashContextFeedback<C> {
+    pub fn new_with_observer(time_observer: &TimeObserver) -> Self {
+        Self {
+            time_observer_name: time_observer.name().to_string(),
+            exec_time: None,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<C, I, S> Feedback<I, S> for CrashContextFeedback<C>
+where
+    C: Corpus<I>,
+    I: Input,
+    S: HasCorpus<C, I>,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<I, S>,
+        OT: ObserversTuple,
+    {
+        self.exec_time = observers
+            .match_name::<TimeObserver>(&self.time_observer_name)
+            .and_then(|observer| *observer.last_runtime());
+        Ok(true)
+    }
+
+    fn append_metadata(&mut self, state: &mut S, testcase: &mut Testcase<I>) -> Result<(), Error> {
+        let parent = *state.corpus().current();
+        let stage = match parent {
+            Some(_) => MUTATION_OP,
+            None => "init",
+        };
+
+        testcase.add_metadata(CrashContextMetadata {
+            parent,
+            stage: stage.to_string(),
+            exec_time: self.exec_time.take(),
+        });
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        self.exec_time = None;
+        Ok(())
+    }
+}
+
+impl<C> Named for CrashContextFeedback<C> {
+    #[inline]
+    fn name(&self) -> &str {
+        "CrashContextFeedback"
+    }
+}
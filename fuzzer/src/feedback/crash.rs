@@ -0,0 +1,94 @@
+use libafl::{
+    bolts::tuples::Named,
+    corpus::Testcase,
+    events::EventFirer,
+    executors::ExitKind,
+    feedbacks::Feedback,
+    inputs::Input,
+    observers::ObserversTuple,
+    state::HasMetadata,
+    Error,
+};
+use serde::{Deserialize, Serialize};
+
+use log::debug;
+
+use crate::{
+    executor::status::ChildStatus,
+    observer::{ExitStatusObserver, EXIT_STATUS_ID},
+};
+
+/// How the target died, attached to every crashing testcase
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ExitStatusMetadata {
+    /// decoded status, None if QEMU itself died and the real status is unknown
+    pub status: Option<ChildStatus>,
+}
+
+libafl::impl_serdeany!(ExitStatusMetadata);
+
+impl ExitStatusMetadata {
+    pub fn signal(&self) -> Option<i32> {
+        self.status.and_then(|s| s.signal())
+    }
+}
+
+/// Same as `CrashFeedback` but also records the signal that killed the target in the testcase
+/// metadata. Relies on an `ExitStatusObserver` being part of the executor observers
+pub struct CrashSignalFeedback {
+    status: Option<ChildStatus>,
+}
+
+impl CrashSignalFeedback {
+    pub fn new() -> Self {
+        Self { status: None }
+    }
+}
+
+impl<I, S> Feedback<I, S> for CrashSignalFeedback
+where
+    I: Input,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<I, S>,
+        OT: ObserversTuple,
+    {
+        if *exit_kind != ExitKind::Crash {
+            return Ok(false);
+        }
+
+        self.status = observers
+            .match_name::<ExitStatusObserver>(EXIT_STATUS_ID)
+            .and_then(|observer| observer.status());
+
+        debug!("CrashSignalFeedback crash with status {:?}", self.status);
+        Ok(true)
+    }
+
+    fn append_metadata(&mut self, _state: &mut S, testcase: &mut Testcase<I>) -> Result<(), Error> {
+        testcase.add_metadata(ExitStatusMetadata {
+            status: self.status.take(),
+        });
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        self.status = None;
+        Ok(())
+    }
+}
+
+impl Named for CrashSignalFeedback {
+    #[inline]
+    fn name(&self) -> &str {
+        "CrashSignalFeedback"
+    }
+}
@@ -1,2 +1,7 @@
 pub mod bitmap;
 pub mod bitmap_state;
+pub mod context;
+pub mod crash;
+pub mod reproduce;
+pub mod stack_hash;
+pub mod suppress;
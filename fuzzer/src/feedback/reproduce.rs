@@ -0,0 +1,176 @@
+use core::cell::RefCell;
+use libafl::{
+    bolts::tuples::Named,
+    corpus::Testcase,
+    events::EventFirer,
+    executors::ExitKind,
+    feedbacks::Feedback,
+    inputs::{HasTargetBytes, Input},
+    observers::{MapObserver, ObserversTuple},
+    state::{HasExecutions, HasMetadata},
+    Error,
+};
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+
+use log::{debug, info};
+
+use crate::{
+    executor::forkserver::{ForkserverRunner, RunResult},
+    observer::SharedMemObserver,
+};
+
+/// How reliably a crash reproduced, attached to every crash that was re-run
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReproductionMetadata {
+    /// number of times the testcase was run again
+    pub runs: usize,
+    /// how many of those runs crashed
+    pub crashes: usize,
+}
+
+libafl::impl_serdeany!(ReproductionMetadata);
+
+/// Wraps a crash feedback and runs every crash it accepts `runs` more times through the
+/// executor's runner. the crash is only accepted if at least one of the runs crashed again.
+/// with `runs` set to 0 the inner feedback decides alone. the runs count as executions, the
+/// coverage map is put back afterwards so the queue feedback still sees the original run. the
+/// runs stop early when a run limit is reached, like any other execution
+pub struct ReproducedCrashFeedback<F> {
+    inner: F,
+    /// name of the coverage observer the runs write into
+    observer_name: String,
+    runner: Rc<RefCell<ForkserverRunner>>,
+    runs: usize,
+    /// runs made for the last crash, fewer than `runs` if a limit was reached
+    done: usize,
+    crashes: usize,
+}
+
+impl<F> ReproducedCrashFeedback<F> {
+    pub fn new(
+        inner: F,
+        observer_name: &str,
+        runner: Rc<RefCell<ForkserverRunner>>,
+        runs: usize,
+    ) -> Self {
+        Self {
+            inner,
+            observer_name: observer_name.to_string(),
+            runner,
+            runs,
+            done: 0,
+            crashes: 0,
+        }
+    }
+
+    /// `executions` is the number of executions before the first run
+    fn reproduce(&mut self, buf: &[u8], executions: usize) -> Result<usize, Error> {
+        let mut runner = self.runner.borrow_mut();
+        let mut crashes = 0;
+        self.done = 0;
+
+        for _ in 0..self.runs {
+            if runner.check_limits(executions + self.done).is_some() {
+                break;
+            }
+
+            self.done += 1;
+            match runner.run(buf)? {
+                RunResult::Status(status) if status.exit_kind() == ExitKind::Crash => crashes += 1,
+                RunResult::Dead => crashes += 1,
+                _ => {}
+            }
+        }
+
+        Ok(crashes)
+    }
+}
+
+impl<F, I, S> Feedback<I, S> for ReproducedCrashFeedback<F>
+where
+    F: Feedback<I, S>,
+    I: Input + HasTargetBytes,
+    S: HasExecutions,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        input: &I,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<I, S>,
+        OT: ObserversTuple,
+    {
+        if !self
+            .inner
+            .is_interesting(state, manager, input, observers, exit_kind)?
+        {
+            return Ok(false);
+        }
+
+        if self.runs == 0 {
+            return Ok(true);
+        }
+
+        let observer = observers
+            .match_name::<SharedMemObserver<u8>>(&self.observer_name)
+            .unwrap();
+        let saved = observer.map().to_vec();
+
+        let reproduced = self.reproduce(input.target_bytes().as_slice(), *state.executions());
+        observer.restore(&saved);
+        *state.executions_mut() += self.done;
+        self.crashes = reproduced?;
+        debug!(
+            "ReproducedCrashFeedback crash reproduced {}/{}",
+            self.crashes, self.done
+        );
+
+        // stopped before the first run, keep the crash rather than lose it. the executor
+        // refuses to run anything else so the fuzzer stops right after saving it
+        if self.done == 0 {
+            return Ok(true);
+        }
+
+        if self.crashes == 0 {
+            info!(
+                "[!] crash did not reproduce in {} runs, dropping it",
+                self.done
+            );
+            self.inner.discard_metadata(state, input)?;
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    fn append_metadata(&mut self, state: &mut S, testcase: &mut Testcase<I>) -> Result<(), Error> {
+        self.inner.append_metadata(state, testcase)?;
+
+        if self.done > 0 {
+            testcase.add_metadata(ReproductionMetadata {
+                runs: self.done,
+                crashes: self.crashes,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, state: &mut S, input: &I) -> Result<(), Error> {
+        self.done = 0;
+        self.crashes = 0;
+        self.inner.discard_metadata(state, input)
+    }
+}
+
+impl<F> Named for ReproducedCrashFeedback<F> {
+    #[inline]
+    fn name(&self) -> &str {
+        "ReproducedCrashFeedback"
+    }
+}
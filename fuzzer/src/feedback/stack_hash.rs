@@ -0,0 +1,137 @@
+use core::cell::RefCell;
+use libafl::{
+    bolts::tuples::Named,
+    corpus::Testcase,
+    events::EventFirer,
+    executors::ExitKind,
+    feedbacks::Feedback,
+    inputs::{HasTargetBytes, Input},
+    observers::ObserversTuple,
+    state::HasMetadata,
+    Error,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, path::PathBuf, rc::Rc};
+
+use log::{debug, info};
+
+use crate::executor::triage::{Backtrace, CrashTriage};
+
+/// Where a crash happened, attached to every crashing testcase that went through triage
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StackHashMetadata {
+    pub hash: u64,
+    /// faulting block first, see `Backtrace`
+    pub pcs: Vec<u64>,
+}
+
+libafl::impl_serdeany!(StackHashMetadata);
+
+/// Replays crashes with a `CrashTriage` and only finds a crash interesting if its backtrace
+/// was not seen before. without a triage every crash is interesting, so the feedback can stay
+/// in the objective when triage is turned off
+pub struct StackHashFeedback {
+    triage: Option<Rc<RefCell<CrashTriage>>>,
+    /// directory of the solutions corpus, crash files are named after their stack hash
+    solutions_dir: PathBuf,
+    /// shared with the fuzz loop, which saves it for --resume
+    seen_hashes: Rc<RefCell<HashSet<u64>>>,
+    backtrace: Option<Backtrace>,
+}
+
+impl StackHashFeedback {
+    pub fn new(triage: Option<Rc<RefCell<CrashTriage>>>, solutions_dir: PathBuf) -> Self {
+        Self {
+            triage,
+            solutions_dir,
+            seen_hashes: Rc::new(RefCell::new(HashSet::new())),
+            backtrace: None,
+        }
+    }
+
+    /// stack hashes of every crash saved so far
+    pub fn seen_hashes(&self) -> Rc<RefCell<HashSet<u64>>> {
+        self.seen_hashes.clone()
+    }
+}
+
+impl<I, S> Feedback<I, S> for StackHashFeedback
+where
+    I: Input + HasTargetBytes,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        input: &I,
+        _observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<I, S>,
+        OT: ObserversTuple,
+    {
+        let triage = match &self.triage {
+            Some(triage) => triage,
+            None => return Ok(true),
+        };
+
+        if *exit_kind != ExitKind::Crash {
+            return Ok(false);
+        }
+
+        self.backtrace = triage
+            .borrow_mut()
+            .replay(&input.target_bytes().as_slice().to_vec())?;
+
+        match &self.backtrace {
+            Some(backtrace) => {
+                let interesting = !self.seen_hashes.borrow().contains(&backtrace.hash());
+                debug!(
+                    "StackHashFeedback stack {:016x} interesting? {}",
+                    backtrace.hash(),
+                    interesting
+                );
+                Ok(interesting)
+            }
+            // the replay didn't crash where we can see it, keep the crash rather than lose it
+            None => Ok(true),
+        }
+    }
+
+    fn append_metadata(&mut self, _state: &mut S, testcase: &mut Testcase<I>) -> Result<(), Error> {
+        let backtrace = match self.backtrace.take() {
+            Some(backtrace) => backtrace,
+            None => return Ok(()),
+        };
+
+        let hash = backtrace.hash();
+        info!(
+            "[+] new crash stack {:016x} faulting at {:#x}",
+            hash,
+            backtrace.faulting_pc().unwrap_or(0)
+        );
+
+        let name = format!("id:{:06},stack:{:016x}", self.seen_hashes.borrow().len(), hash);
+        testcase.set_filename(self.solutions_dir.join(name).to_string_lossy().to_string());
+        testcase.add_metadata(StackHashMetadata {
+            hash,
+            pcs: backtrace.pcs,
+        });
+
+        self.seen_hashes.borrow_mut().insert(hash);
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        self.backtrace = None;
+        Ok(())
+    }
+}
+
+impl Named for StackHashFeedback {
+    #[inline]
+    fn name(&self) -> &str {
+        "StackHashFeedback"
+    }
+}
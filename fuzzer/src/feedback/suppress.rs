@@ -0,0 +1,112 @@
+use core::{cell::RefCell, marker::PhantomData};
+use libafl::{
+    bolts::tuples::Named,
+    corpus::Testcase,
+    events::{Event, EventFirer},
+    executors::ExitKind,
+    feedbacks::Feedback,
+    inputs::{HasTargetBytes, Input},
+    observers::ObserversTuple,
+    stats::UserStats,
+    Error,
+};
+use std::rc::Rc;
+
+use log::{debug, info};
+
+use crate::{executor::triage::CrashTriage, suppress::Suppressions};
+
+/// name of the user stat counting crashes that matched the suppression list
+pub const SUPPRESSED_STAT: &str = "suppressed_crashes";
+
+/// Finds every input interesting except crashes matching the suppression list. Used in the
+/// objective to keep known crashes out of the crash directory, and optionally in the queue
+/// feedback to keep them out of the queue. only the instance that counts reports the stat
+pub struct SuppressionFeedback {
+    /// None turns the feedback into a no-op
+    triage: Option<Rc<RefCell<CrashTriage>>>,
+    suppressions: Rc<Suppressions>,
+    count: bool,
+    suppressed: u64,
+}
+
+impl SuppressionFeedback {
+    pub fn new(
+        triage: Option<Rc<RefCell<CrashTriage>>>,
+        suppressions: Rc<Suppressions>,
+        count: bool,
+    ) -> Self {
+        Self {
+            triage,
+            suppressions,
+            count,
+            suppressed: 0,
+        }
+    }
+}
+
+impl<I, S> Feedback<I, S> for SuppressionFeedback
+where
+    I: Input + HasTargetBytes,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        input: &I,
+        _observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<I, S>,
+        OT: ObserversTuple,
+    {
+        let triage = match &self.triage {
+            Some(triage) if *exit_kind == ExitKind::Crash && !self.suppressions.is_empty() => {
+                triage
+            }
+            _ => return Ok(true),
+        };
+
+        let backtrace = triage
+            .borrow_mut()
+            .replay(&input.target_bytes().as_slice().to_vec())?;
+
+        let suppressed = backtrace.map_or(false, |b| self.suppressions.matches(&b));
+        if !suppressed {
+            return Ok(true);
+        }
+
+        debug!("SuppressionFeedback crash matched the suppression list");
+        if self.count {
+            self.suppressed += 1;
+            info!("[!] known crash suppressed. suppressed={}", self.suppressed);
+
+            manager.fire(
+                state,
+                Event::UpdateUserStats {
+                    name: SUPPRESSED_STAT.to_string(),
+                    value: UserStats::Number(self.suppressed),
+                    phantom: PhantomData,
+                },
+            )?;
+        }
+
+        Ok(false)
+    }
+
+    fn append_metadata(&mut self, _state: &mut S, _testcase: &mut Testcase<I>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Named for SuppressionFeedback {
+    #[inline]
+    fn name(&self) -> &str {
+        "SuppressionFeedback"
+    }
+}
@@ -0,0 +1,157 @@
+use std::{fmt, ops::Range};
+
+use libafl::Error;
+use log::info;
+
+use crate::{elf, suppress::parse_hex};
+
+/// Part of the target binary to instrument (or leave alone), as written in the config:
+///
+/// ```text
+/// inst_targets = sym:parse_header, section:.text, range:0x1a000-0x1b000
+/// ```
+///
+/// ranges are offsets into the binary like `sym:` and `section:` addresses, the load base of
+/// a PIE target is added when they are resolved
+#[derive(Debug, Clone, PartialEq)]
+pub enum RangeSpec {
+    Symbol(String),
+    Section(String),
+    Range(u64, u64),
+}
+
+impl RangeSpec {
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if let Some(name) = s.strip_prefix("sym:") {
+            return Some(RangeSpec::Symbol(name.to_string()));
+        }
+
+        if let Some(name) = s.strip_prefix("section:") {
+            return Some(RangeSpec::Section(name.to_string()));
+        }
+
+        let range = s.strip_prefix("range:")?;
+        let idx = range.find('-')?;
+        let start = parse_hex(&range[..idx])?;
+        let end = parse_hex(&range[idx + 1..])?;
+        if start >= end {
+            return None;
+        }
+
+        Some(RangeSpec::Range(start, end))
+    }
+
+    /// comma separated list, like the value of `inst_targets`
+    pub fn parse_list(s: &str) -> Option<Vec<Self>> {
+        s.split(',')
+            .filter(|spec| !spec.trim().is_empty())
+            .map(Self::parse)
+            .collect()
+    }
+
+    /// guest addresses covered by this part of the target
+    pub fn resolve(&self, target: &str, load_base: u64) -> Result<Range<u64>, Error> {
+        let range = match self {
+            RangeSpec::Symbol(name) => elf::find_sym_range(target, name),
+            RangeSpec::Section(name) => elf::find_section_range(target, name),
+            RangeSpec::Range(start, end) => Ok(*start..*end),
+        }
+        .map_err(|e| Error::IllegalArgument(format!("Can't resolve {}: {}", self, e)))?;
+
+        Ok(load_base + range.start..load_base + range.end)
+    }
+}
+
+impl fmt::Display for RangeSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RangeSpec::Symbol(name) => write!(f, "sym:{}", name),
+            RangeSpec::Section(name) => write!(f, "section:{}", name),
+            RangeSpec::Range(start, end) => write!(f, "range:{:#x}-{:#x}", start, end),
+        }
+    }
+}
+
+/// resolve every spec to guest addresses. QEMU maps a PIE target at `pie_base`, other targets
+/// are mapped where their headers say
+pub fn resolve(
+    target: &str,
+    specs: &[RangeSpec],
+    pie_base: u64,
+) -> Result<Vec<Range<u64>>, Error> {
+    if specs.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let load_base = elf::load_base(target, pie_base)
+        .map_err(|e| Error::IllegalArgument(format!("Can't read {}: {}", target, e)))?;
+
+    specs
+        .iter()
+        .map(|spec| {
+            let range = spec.resolve(target, load_base)?;
+            info!("[+] {} is at {:#x}-{:#x}", spec, range.start, range.end);
+            Ok(range)
+        })
+        .collect()
+}
+
+/// the format of AFL_QEMU_INST_RANGES and AFL_QEMU_EXCLUDE_RANGES: `start-end` pairs
+/// separated by commas
+pub fn format_ranges(ranges: &[Range<u64>]) -> String {
+    ranges
+        .iter()
+        .map(|r| format!("{:#x}-{:#x}", r.start, r.end))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_specs() {
+        assert_eq!(
+            RangeSpec::parse(" sym:parse_header"),
+            Some(RangeSpec::Symbol("parse_header".to_string()))
+        );
+        assert_eq!(
+            RangeSpec::parse("section:.text"),
+            Some(RangeSpec::Section(".text".to_string()))
+        );
+        assert_eq!(
+            RangeSpec::parse("range:0x1a000-1b000"),
+            Some(RangeSpec::Range(0x1a000, 0x1b000))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_specs() {
+        assert_eq!(RangeSpec::parse("parse_header"), None);
+        assert_eq!(RangeSpec::parse("range:0x1000"), None);
+        assert_eq!(RangeSpec::parse("range:0x2000-0x1000"), None);
+        assert_eq!(RangeSpec::parse("range:0x1000-zzz"), None);
+    }
+
+    #[test]
+    fn parses_lists() {
+        assert_eq!(
+            RangeSpec::parse_list("sym:main, range:0x10-0x20,"),
+            Some(vec![
+                RangeSpec::Symbol("main".to_string()),
+                RangeSpec::Range(0x10, 0x20)
+            ])
+        );
+        assert_eq!(RangeSpec::parse_list("sym:main, bogus"), None);
+    }
+
+    #[test]
+    fn formats_ranges_for_qemu() {
+        assert_eq!(
+            format_ranges(&[0x10..0x20, 0x5500001000..0x5500002000]),
+            "0x10-0x20,0x5500001000-0x5500002000"
+        );
+    }
+}
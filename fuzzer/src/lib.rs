@@ -1,11 +1,20 @@
 // fuzzer core logic
 pub mod observer;
 pub mod outfile;
+pub mod shmem_input;
 pub mod pipe;
 pub mod executor;
 pub mod feedback;
 pub mod stats;
 pub mod power;
+pub mod coverage;
+pub mod resume;
+pub mod queue;
+pub mod suppress;
+pub mod solutions;
+pub mod signals;
+pub mod limits;
+pub mod inst_ranges;
 
 // utilities
 pub mod elf;
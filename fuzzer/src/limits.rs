@@ -0,0 +1,72 @@
+use std::{fmt, time::Duration};
+
+use libafl::bolts::current_time;
+
+use crate::{executor::status::signal_name, signals};
+
+/// Why the fuzzer has to stop
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    MaxTime,
+    MaxExecs,
+    /// SIGINT or SIGTERM
+    Signal(i32),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::MaxTime => write!(f, "reached max_time"),
+            Limit::MaxExecs => write!(f, "reached max_execs"),
+            Limit::Signal(sig) => write!(f, "received {}", signal_name(*sig)),
+        }
+    }
+}
+
+/// When to stop fuzzing. the executor checks the limits before every execution, a stage runs
+/// many of them so checking between stage rounds would overshoot
+#[derive(Debug, Clone)]
+pub struct RunLimits {
+    max_time: Option<Duration>,
+    max_execs: Option<u64>,
+    start_time: Duration,
+}
+
+impl RunLimits {
+    /// `max_time` counts from now
+    pub fn new(max_time: Option<Duration>, max_execs: Option<u64>) -> Self {
+        Self {
+            max_time,
+            max_execs,
+            start_time: current_time(),
+        }
+    }
+
+    /// only stop when a signal asks us to
+    pub fn unlimited() -> Self {
+        Self::new(None, None)
+    }
+
+    /// the limit reached before execution number `executions + 1`, if any
+    pub fn check(&self, executions: usize) -> Option<Limit> {
+        if let Some(sig) = signals::stop_signal() {
+            return Some(Limit::Signal(sig));
+        }
+
+        if self
+            .max_time
+            .map_or(false, |max| current_time() - self.start_time >= max)
+        {
+            return Some(Limit::MaxTime);
+        }
+
+        if self
+            .max_execs
+            .map_or(false, |max| executions as u64 >= max)
+        {
+            return Some(Limit::MaxExecs);
+        }
+
+        None
+    }
+}
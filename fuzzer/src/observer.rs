@@ -15,6 +15,11 @@ use libafl::{
     Error,
 };
 
+use crate::executor::status::ChildStatus;
+
+/// well known name of the `ExitStatusObserver`, the executor looks it up by this name
+pub const EXIT_STATUS_ID: &str = "exit_status";
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(bound = "T: serde::de::DeserializeOwned")]
 #[allow(clippy::unsafe_derive_deserialize)]
@@ -24,6 +29,10 @@ where
 {
     initial: T,
     map: OwnedArrayPtrMut<T>,
+    /// size of the underlying shared memory, the map can not grow beyond it
+    capacity: usize,
+    /// env variable the shared memory id is exported with
+    env_shmem_key: String,
     name: String,
     // total_coverage_edges: usize,
 }
@@ -130,6 +139,19 @@ where
 {
     /// Creates a new MapObserver
     pub fn new(name: &'static str, env_shmem_key: &str, map_size: usize) -> Self {
+        let (ptr, len) = Self::alloc_shmem(env_shmem_key, map_size);
+
+        Self {
+            name: name.to_string(),
+            map: OwnedArrayPtrMut::ArrayPtr((ptr, len)),
+            capacity: len,
+            env_shmem_key: env_shmem_key.to_string(),
+            initial: unsafe { *ptr },
+        }
+    }
+
+    /// create a zeroed shared memory and export it to the environment
+    fn alloc_shmem(env_shmem_key: &str, map_size: usize) -> (*mut T, usize) {
         let mut shmem = StdShMemProvider::new()
             .unwrap()
             .new_map(map_size)
@@ -150,10 +172,115 @@ where
             }
         }
 
+        (ptr, shmem.len())
+    }
+
+    /// size of the underlying shared memory
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// replace the shared memory with a bigger one. the new id is exported to the environment,
+    /// so QEMU has to be restarted to pick it up
+    pub fn grow(&mut self, map_size: usize) {
+        let (ptr, len) = Self::alloc_shmem(&self.env_shmem_key, map_size);
+
+        self.map = OwnedArrayPtrMut::ArrayPtr((ptr, len));
+        self.capacity = len;
+    }
+
+    /// put the map back to `saved`, an earlier copy of `map()`. used after runs that must not
+    /// count, like crash reproduction, which only get a shared reference to the observers
+    pub fn restore(&self, saved: &[T]) {
+        // write through the shared memory pointer from `alloc_shmem`, never through a slice
+        // borrowed from the map. the target writes to the same memory behind our back anyway
+        let (ptr, len) = match &self.map {
+            OwnedArrayPtrMut::ArrayPtr((ptr, len)) => (*ptr, *len),
+            // a deserialized map is not shared memory, no run wrote into it
+            _ => return,
+        };
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(saved.as_ptr(), ptr, saved.len().min(len));
+        }
+    }
+
+    /// use only the first `map_size` bytes of the shared memory, for example when the target
+    /// reports a smaller map than we allocated
+    pub fn set_map_size(&mut self, map_size: usize) -> Result<(), Error> {
+        if map_size > self.capacity {
+            return Err(Error::IllegalArgument(format!(
+                "target coverage map of {} bytes is larger than the shared memory ({} bytes). \
+                set map_size = {} in the config",
+                map_size, self.capacity, map_size
+            )));
+        }
+
+        let ptr = self.map.as_mut_slice().as_mut_ptr();
+        self.map = OwnedArrayPtrMut::ArrayPtr((ptr, map_size));
+        Ok(())
+    }
+}
+
+/// Holds the decoded exit status of the last target run. The executor fills it in after each
+/// execution so feedbacks can tell how the target died
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ExitStatusObserver {
+    name: String,
+    status: Option<ChildStatus>,
+}
+
+impl ExitStatusObserver {
+    pub fn new() -> Self {
         Self {
-            name: name.to_string(),
-            map: OwnedArrayPtrMut::ArrayPtr((ptr, shmem.len())),
-            initial: unsafe { *ptr },
+            name: EXIT_STATUS_ID.to_string(),
+            status: None,
         }
     }
+
+    /// status of the last execution, None if it is unknown (for example QEMU itself died)
+    pub fn status(&self) -> Option<ChildStatus> {
+        self.status
+    }
+
+    pub fn set_status(&mut self, status: Option<ChildStatus>) {
+        self.status = status;
+    }
+}
+
+impl Observer for ExitStatusObserver {}
+
+impl<EM, I, S, Z> HasExecHooks<EM, I, S, Z> for ExitStatusObserver
+where
+    I: Input,
+{
+    #[inline]
+    fn pre_exec(
+        &mut self,
+        _fuzzer: &mut Z,
+        _state: &mut S,
+        _mgr: &mut EM,
+        _input: &I,
+    ) -> Result<(), Error> {
+        self.status = None;
+        Ok(())
+    }
+
+    #[inline]
+    fn post_exec(
+        &mut self,
+        _fuzzer: &mut Z,
+        _state: &mut S,
+        _mgr: &mut EM,
+        _input: &I,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Named for ExitStatusObserver {
+    #[inline]
+    fn name(&self) -> &str {
+        self.name.as_str()
+    }
 }
@@ -1,10 +1,34 @@
 use std::{
-    fs::{File, OpenOptions},
+    fs::{self, File, OpenOptions},
     io::{prelude::*, SeekFrom},
+    path::{Path, PathBuf},
+    process,
 };
 
+use log::{debug, warn};
+
+/// What to do with testcases larger than the input file's `max_len`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OversizePolicy {
+    /// cut the testcase down to `max_len` bytes
+    Truncate,
+    /// don't run the testcase at all
+    Reject,
+}
+
+impl OversizePolicy {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "truncate" => Some(OversizePolicy::Truncate),
+            "reject" => Some(OversizePolicy::Reject),
+            _ => None,
+        }
+    }
+}
+
 pub struct OutFile {
     file: File,
+    path: PathBuf,
     max_len: u64, 
 }
 
@@ -16,7 +40,33 @@ impl OutFile {
             .create(true)
             .open(file_name)
             .expect("Failed to open the input file");
-        Self { file: f, max_len }
+        Self { file: f, path: PathBuf::from(file_name), max_len }
+    }
+
+    /// create an input file with a name unique to this fuzzer instance, so several fuzzers can
+    /// run from the same directory. `dir` can point to a tmpfs to spare the disk
+    pub fn new_unique(dir: Option<&Path>, max_len: u64) -> Self {
+        Self::new_unique_with_prefix(dir, ".cur_input", max_len)
+    }
+
+    /// same as `new_unique` for files other than the executor's input file
+    pub fn new_unique_with_prefix(dir: Option<&Path>, prefix: &str, max_len: u64) -> Self {
+        let name = format!("{}-{}", prefix, process::id());
+        let path = match dir {
+            Some(dir) => dir.join(name),
+            None => PathBuf::from(name),
+        };
+
+        debug!("[*] input file {:?}", path);
+        Self::new(path.to_str().expect("Invalid input file path"), max_len)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn max_len(&self) -> u64 {
+        self.max_len
     }
 
     pub fn write_buf(&mut self, buf: &Vec<u8>) {
@@ -35,4 +85,18 @@ impl OutFile {
     pub fn rewind(&mut self) {
         self.file.seek(SeekFrom::Start(0)).unwrap();
     }
+
+    /// a new handle sharing the same open file (and file offset), used to hand the file to
+    /// the target as its stdin
+    pub fn try_clone_file(&self) -> std::io::Result<File> {
+        self.file.try_clone()
+    }
+}
+
+impl Drop for OutFile {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("Failed to remove input file {:?}: {}", self.path, e);
+        }
+    }
 }
@@ -1,5 +1,7 @@
 use libc;
+use std::io;
 use std::os::raw::c_int;
+use std::time::{Duration, Instant};
 use log::debug;
 
 #[derive(Debug, Clone)]
@@ -10,77 +12,169 @@ pub struct Pipe {
     dups: Vec<c_int>,
 }
 
+/// run a libc call returning -1 on error, retrying it as long as it fails with EINTR
+fn retry_eintr<F: FnMut() -> isize>(mut f: F) -> io::Result<isize> {
+    loop {
+        let ret = f();
+        if ret >= 0 {
+            return Ok(ret);
+        }
+
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
 impl Pipe {
-    pub fn new(name: String) -> Self {
+    pub fn new(name: String) -> io::Result<Self> {
         let mut fds = [-1 as c_int, -1 as c_int];
         let ret = unsafe { libc::pipe(fds.as_mut_ptr()) };
         if ret < 0 {
-            panic!("pipe() failed");
+            return Err(io::Error::last_os_error());
         }
 
         debug!("[*] new pipe {} ({},{})", name, fds[0], fds[1]);
-        Self {
+        Ok(Self {
             read_end: fds[0],
             write_end: fds[1],
             name,
             dups: Vec::new(),
-        }
+        })
+    }
+
+    pub fn read_fd(&self) -> c_int {
+        self.read_end
     }
 
-    pub fn write_i32(&self, value: i32) -> isize {
+    pub fn write_i32(&self, value: i32) -> io::Result<()> {
         // debug!("[+] {} write_i32({});", self.name, value);
-        let v: i32 = value; // store a copy
-        let rlen = unsafe {
-            libc::write(
-                self.write_end,
-                (&v) as *const libc::c_int as *mut libc::c_void,
-                4
-            )
-        };
+        let buf = value.to_ne_bytes();
+        let mut offset = 0;
+
+        while offset < buf.len() {
+            let wlen = retry_eintr(|| unsafe {
+                libc::write(
+                    self.write_end,
+                    buf[offset..].as_ptr() as *const libc::c_void,
+                    buf.len() - offset
+                )
+            })?;
+
+            offset += wlen as usize;
+        }
 
-        return rlen;
+        Ok(())
     }
 
-    pub fn read_i32(&self) -> i32 {
-        // debug!("[+] {} read_i32()...", self.name); 
-        let mut value: i32 = 0;
-        let rlen;
-        unsafe {
-            rlen = libc::read(
-                self.read_end,
-                (&mut value) as *mut libc::c_int as *mut libc::c_void,
-                4
-            );
-        }
+    pub fn read_i32(&self) -> io::Result<i32> {
+        // debug!("[+] {} read_i32()...", self.name);
+        let mut buf = [0u8; 4];
+        self.read_exact(&mut buf)?;
 
         // debug!("[+] {} read_i32() = {};", self.name, value);
+        Ok(i32::from_ne_bytes(buf))
+    }
+
+    /// wait until the read end has data (or EOF) or `timeout` passes
+    pub fn poll_read(&self, timeout: Duration) -> io::Result<bool> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let mut pfd = libc::pollfd {
+                fd: self.read_end,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let ret = unsafe { libc::poll(&mut pfd, 1, remaining.as_millis() as c_int) };
+
+            if ret > 0 {
+                return Ok(true);
+            }
+
+            if ret == 0 {
+                return Ok(false);
+            }
 
-        assert_eq!(rlen, 4);
-        return value;
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
     }
 
-    pub fn dup_read(&mut self, dst_fd: i32) {
+    /// read exactly `len` bytes. fails with `TimedOut` if they did not all arrive within `timeout`
+    pub fn read_bytes_timeout(&self, len: usize, timeout: Duration) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0u8; len];
+        self.read_exact_until(&mut buf, Some(Instant::now() + timeout))?;
+        Ok(buf)
+    }
+
+    fn read_exact(&self, buf: &mut [u8]) -> io::Result<()> {
+        self.read_exact_until(buf, None)
+    }
+
+    fn read_exact_until(&self, buf: &mut [u8], deadline: Option<Instant>) -> io::Result<()> {
+        let mut offset = 0;
+
+        while offset < buf.len() {
+            if let Some(deadline) = deadline {
+                if !self.poll_read(deadline.saturating_duration_since(Instant::now()))? {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("timed out reading from {}", self.name),
+                    ));
+                }
+            }
+
+            let rlen = retry_eintr(|| unsafe {
+                libc::read(
+                    self.read_end,
+                    buf[offset..].as_mut_ptr() as *mut libc::c_void,
+                    buf.len() - offset
+                )
+            })?;
+
+            if rlen == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("{} was closed", self.name),
+                ));
+            }
+
+            offset += rlen as usize;
+        }
+
+        Ok(())
+    }
+
+    pub fn dup_read(&mut self, dst_fd: i32) -> io::Result<()> {
         let ret = unsafe {
             libc::dup2(self.read_end, dst_fd)
         };
 
         if ret < 0 {
-            panic!("dup2() failed");
+            return Err(io::Error::last_os_error());
         }
 
         self.dups.push(dst_fd);
+        Ok(())
     }
 
-    pub fn dup_write(&mut self, dst_fd: i32) {
+    pub fn dup_write(&mut self, dst_fd: i32) -> io::Result<()> {
         let ret = unsafe {
             libc::dup2(self.write_end, dst_fd)
         };
 
         if ret < 0 {
-            panic!("dup2() failed");
+            return Err(io::Error::last_os_error());
         }
 
         self.dups.push(dst_fd);
+        Ok(())
     }
 
     #[allow(dead_code)]
@@ -95,9 +189,9 @@ impl Pipe {
 
     #[allow(dead_code)]
     pub fn close_write(&mut self) {
-        debug!("closing write_end={}", self.read_end);
+        debug!("closing write_end={}", self.write_end);
         unsafe {
-            libc::close(self.read_end);
+            libc::close(self.write_end);
         }
 
         self.write_end = -1;
@@ -111,8 +205,6 @@ impl Drop for Pipe{
         unsafe {
             libc::close(self.read_end);
             libc::close(self.write_end);
-            libc::close(self.read_end);
-            libc::close(self.write_end);
 
             for fd in self.dups.iter() {
                 libc::close(*fd);
@@ -120,3 +212,30 @@ impl Drop for Pipe{
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_bytes_that_arrived() {
+        let pipe = Pipe::new("test pipe".to_string()).unwrap();
+        pipe.write_i32(0x01020304).unwrap();
+
+        let data = pipe
+            .read_bytes_timeout(4, Duration::from_millis(100))
+            .unwrap();
+        assert_eq!(data, 0x01020304i32.to_ne_bytes());
+    }
+
+    #[test]
+    fn read_bytes_times_out_on_a_short_write() {
+        let pipe = Pipe::new("test pipe".to_string()).unwrap();
+        pipe.write_i32(0).unwrap();
+
+        let err = pipe
+            .read_bytes_timeout(8, Duration::from_millis(50))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+}
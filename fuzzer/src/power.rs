@@ -68,6 +68,15 @@ where
         }
     }
 
+    /// how many testcases reached each path so far, saved so a resumed run keeps its schedule
+    pub fn paths(&self) -> &HashMap<u64, usize> {
+        &self.paths
+    }
+
+    pub fn set_paths(&mut self, paths: HashMap<u64, usize>) {
+        self.paths = paths;
+    }
+
     /// Gets the number of iterations this mutator should run for.
     fn iterations(&self, case: &Testcase<I>) -> Result<usize, Error> {
         let meta: &MapIndexesMetadata =
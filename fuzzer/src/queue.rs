@@ -0,0 +1,279 @@
+use core::cell::RefCell;
+use libafl::{
+    bolts::current_time,
+    corpus::{Corpus, Testcase},
+    inputs::{HasBytesVec, Input},
+    state::HasMetadata,
+    Error,
+};
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fs,
+    hash::Hasher,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::feedback::bitmap::NewCoverageMetadata;
+
+/// the only mutational stage we run, AFL writes the stage name into the file name
+pub const MUTATION_OP: &str = "havoc";
+
+/// Keeps the queue in memory like `InMemoryCorpus` and, if a directory is given, also writes
+/// every testcase to it with AFL style file names (`id:000123,src:000045,op:havoc,...`) so AFL++
+/// tools can read the queue
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(bound = "I: serde::de::DeserializeOwned")]
+pub struct QueueCorpus<I>
+where
+    I: Input,
+{
+    entries: Vec<RefCell<Testcase<I>>>,
+    current: Option<usize>,
+    dir_path: Option<PathBuf>,
+    /// id of the first entry we write, one past the highest id already in `dir_path` so the
+    /// queue of an older run is not overwritten
+    first_id: usize,
+    /// content hash of every entry in `dir_path`. a testcase that is already there is not
+    /// written again, a resumed run adds the whole saved queue once more
+    saved: HashMap<u64, PathBuf>,
+    /// used for the `time:` part of file names
+    start_time: Duration,
+}
+
+impl<I> QueueCorpus<I>
+where
+    I: Input,
+{
+    pub fn new(dir_path: Option<PathBuf>) -> Self {
+        let first_id = dir_path.as_deref().map_or(0, next_free_id);
+        if first_id > 0 {
+            debug!(
+                "[*] queue directory has older entries, numbering from {}",
+                first_id
+            );
+        }
+
+        let saved = dir_path.as_deref().map(saved_entries).unwrap_or_default();
+
+        Self {
+            entries: Vec::new(),
+            current: None,
+            dir_path,
+            first_id,
+            saved,
+            start_time: current_time(),
+        }
+    }
+
+    /// AFL style name for the testcase that is about to get `id`. while nothing is being fuzzed
+    /// (the initial dry run) testcases come from the input corpus
+    fn file_name(&self, id: usize, testcase: &Testcase<I>) -> String {
+        let time = (current_time() - self.start_time).as_millis();
+
+        let id = self.first_id + id;
+        let mut name = match self.current {
+            Some(src) => format!(
+                "id:{:06},src:{:06},op:{},time:{}",
+                id,
+                self.first_id + src,
+                MUTATION_OP,
+                time
+            ),
+            None => format!("id:{:06},time:{},orig", id, time),
+        };
+
+        let new_edges = testcase
+            .metadata()
+            .get::<NewCoverageMetadata>()
+            .map_or(false, |m| m.new_edges);
+        if new_edges {
+            name.push_str(",+cov");
+        }
+
+        name
+    }
+}
+
+/// one past the highest `id:` of the AFL style file names in `dir`, 0 if there are none
+fn next_free_id(dir: &Path) -> usize {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| parse_id(&entry.file_name().to_string_lossy()))
+        .map(|id| id + 1)
+        .max()
+        .unwrap_or(0)
+}
+
+/// content hash of every AFL style entry in `dir` and its path
+fn saved_entries(dir: &Path) -> HashMap<u64, PathBuf> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return HashMap::new(),
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| parse_id(&entry.file_name().to_string_lossy()).is_some())
+        .filter_map(|entry| {
+            let data = fs::read(entry.path()).ok()?;
+            Some((content_hash(&data), entry.path()))
+        })
+        .collect()
+}
+
+fn content_hash(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(data);
+    hasher.finish()
+}
+
+/// the id of a file name like `id:000123,src:000045,...`
+fn parse_id(name: &str) -> Option<usize> {
+    let id = name.strip_prefix("id:")?;
+    let end = id.find(',').unwrap_or_else(|| id.len());
+    id[..end].parse().ok()
+}
+
+impl<I> Corpus<I> for QueueCorpus<I>
+where
+    I: Input + HasBytesVec,
+{
+    #[inline]
+    fn count(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn add(&mut self, mut testcase: Testcase<I>) -> Result<usize, Error> {
+        let id = self.entries.len();
+
+        if let Some(dir_path) = &self.dir_path {
+            let name = self.file_name(id, &testcase);
+            let input = testcase.load_input()?;
+            let hash = content_hash(input.bytes());
+
+            let path = match self.saved.get(&hash) {
+                Some(path) => {
+                    debug!("[*] queue entry is already saved as {:?}", path);
+                    path.clone()
+                }
+                None => {
+                    let path = dir_path.join(name);
+                    debug!("[*] saving queue entry {:?}", path);
+                    input.to_file(&path)?;
+                    self.saved.insert(hash, path.clone());
+                    path
+                }
+            };
+
+            testcase.set_filename(path.to_string_lossy().to_string());
+        }
+
+        self.entries.push(RefCell::new(testcase));
+        Ok(id)
+    }
+
+    /// the file on disk keeps the name of the testcase it was first written for
+    fn replace(&mut self, idx: usize, testcase: Testcase<I>) -> Result<(), Error> {
+        if idx >= self.entries.len() {
+            return Err(Error::KeyNotFound(format!("Index {} out of bounds", idx)));
+        }
+
+        self.entries[idx] = RefCell::new(testcase);
+        Ok(())
+    }
+
+    /// removing shifts the ids of later testcases, the files on disk are left alone
+    fn remove(&mut self, idx: usize) -> Result<Option<Testcase<I>>, Error> {
+        if idx >= self.entries.len() {
+            return Ok(None);
+        }
+
+        Ok(Some(self.entries.remove(idx).into_inner()))
+    }
+
+    #[inline]
+    fn get(&self, idx: usize) -> Result<&RefCell<Testcase<I>>, Error> {
+        Ok(&self.entries[idx])
+    }
+
+    #[inline]
+    fn current(&self) -> &Option<usize> {
+        &self.current
+    }
+
+    #[inline]
+    fn current_mut(&mut self) -> &mut Option<usize> {
+        &mut self.current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libafl::inputs::BytesInput;
+    use std::{env, process};
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("queue-test-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn parses_ids() {
+        assert_eq!(parse_id("id:000123,src:000045,op:havoc,time:10"), Some(123));
+        assert_eq!(parse_id("id:000007"), Some(7));
+        assert_eq!(parse_id("id_0000000000000007"), None);
+        assert_eq!(parse_id("id:abc,orig"), None);
+        assert_eq!(parse_id("README"), None);
+    }
+
+    #[test]
+    fn numbers_after_the_highest_id() {
+        let dir = test_dir("next-id");
+        assert_eq!(next_free_id(&dir), 0);
+
+        // gaps don't matter and other files are ignored
+        let names = [
+            "id:000000,orig",
+            "id:000005,src:000000",
+            "id_000099",
+            "notes",
+        ];
+        for name in names.iter() {
+            fs::write(dir.join(name), b"x").unwrap();
+        }
+        assert_eq!(next_free_id(&dir), 6);
+
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(next_free_id(&dir), 0);
+    }
+
+    #[test]
+    fn does_not_save_an_entry_twice() {
+        let dir = test_dir("dedup");
+        fs::write(dir.join("id:000000,time:0,orig"), b"old").unwrap();
+
+        let mut queue = QueueCorpus::new(Some(dir.clone()));
+        for data in [b"old", b"new", b"new"].iter() {
+            let testcase = Testcase::new(BytesInput::new(data.to_vec()));
+            queue.add(testcase).unwrap();
+        }
+
+        let files = fs::read_dir(&dir).unwrap().count();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(queue.count(), 3);
+        assert_eq!(files, 2);
+    }
+}
@@ -0,0 +1,170 @@
+use libafl::{
+    bolts::tuples::MatchName,
+    corpus::Corpus,
+    feedbacks::FeedbackStatesTuple,
+    inputs::{HasBytesVec, Input},
+    state::{HasCorpus, HasFeedbackStates},
+    Error,
+};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    fs,
+    hash::Hasher,
+    path::Path,
+};
+
+use crate::feedback::bitmap_state::CoverageFeedbackState;
+
+/// file inside the state directory holding the serialized `ResumeState`
+pub const STATE_FILE: &str = "fuzzer_state.json";
+
+/// everything we need on top of the queue to continue a campaign where it stopped
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ResumeState {
+    /// all-time coverage, one entry per coverage feedback state
+    pub feedback_states: Vec<CoverageFeedbackState>,
+    /// path counts of the power schedule
+    pub paths: HashMap<u64, usize>,
+    /// stack hashes of the crashes triage already saved. missing from states saved before
+    /// triage kept them
+    #[serde(default)]
+    pub stack_hashes: HashSet<u64>,
+}
+
+impl ResumeState {
+    pub fn new(
+        feedback_states: Vec<CoverageFeedbackState>,
+        paths: HashMap<u64, usize>,
+        stack_hashes: HashSet<u64>,
+    ) -> Self {
+        Self {
+            feedback_states,
+            paths,
+            stack_hashes,
+        }
+    }
+
+    /// read the state saved by a previous run, None if there is nothing to resume from
+    pub fn load(dir: &Path) -> Result<Option<Self>, Error> {
+        let path = dir.join(STATE_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let data = fs::read(&path)?;
+        let state = serde_json::from_slice(&data)
+            .map_err(|e| Error::Serialize(format!("Error parsing {:?}: {}", path, e)))?;
+
+        Ok(Some(state))
+    }
+
+    /// write the state next to a temporary file first so a crash while saving does not leave a
+    /// truncated state behind
+    pub fn save(&self, dir: &Path) -> Result<(), Error> {
+        fs::create_dir_all(dir)?;
+
+        let data = serde_json::to_vec(self)
+            .map_err(|e| Error::Serialize(format!("Error serializing fuzzer state: {}", e)))?;
+
+        let path = dir.join(STATE_FILE);
+        let tmp_path = dir.join(format!(".{}.tmp", STATE_FILE));
+        fs::write(&tmp_path, data)?;
+        fs::rename(&tmp_path, &path)?;
+
+        debug!("[*] saved fuzzer state to {:?}", path);
+        Ok(())
+    }
+
+    /// snapshot the coverage feedback states with the given names
+    pub fn collect<FT, S>(
+        state: &S,
+        names: &[&str],
+        paths: HashMap<u64, usize>,
+        stack_hashes: HashSet<u64>,
+    ) -> Self
+    where
+        FT: FeedbackStatesTuple,
+        S: HasFeedbackStates<FT>,
+    {
+        let feedback_states = names
+            .iter()
+            .filter_map(|name| {
+                state
+                    .feedback_states()
+                    .match_name::<CoverageFeedbackState>(name)
+                    .cloned()
+            })
+            .collect();
+
+        Self::new(feedback_states, paths, stack_hashes)
+    }
+
+    /// put the saved coverage back into the state and hand back the power schedule path counts.
+    /// states saved with a different map size are ignored, their coverage ids mean nothing now
+    pub fn restore<FT, S>(self, state: &mut S) -> HashMap<u64, usize>
+    where
+        FT: FeedbackStatesTuple,
+        S: HasFeedbackStates<FT>,
+    {
+        for saved in self.feedback_states {
+            let current = match state
+                .feedback_states_mut()
+                .match_name_mut::<CoverageFeedbackState>(&saved.name)
+            {
+                Some(current) => current,
+                None => {
+                    warn!("[!] no feedback state named {}, ignoring it", saved.name);
+                    continue;
+                }
+            };
+
+            if current.map_size() != saved.map_size() {
+                warn!(
+                    "[!] saved {} has {} coverage ids but the map has {}, ignoring it",
+                    saved.name,
+                    saved.map_size(),
+                    current.map_size()
+                );
+                continue;
+            }
+
+            *current = saved;
+        }
+
+        self.paths
+    }
+}
+
+/// write every testcase of the corpus to `dir`. files are named after a hash of their content
+/// so testcases saved by an earlier save (or an earlier run) are not written twice
+pub fn save_queue<C, I, S>(state: &S, dir: &Path) -> Result<usize, Error>
+where
+    C: Corpus<I>,
+    I: Input + HasBytesVec,
+    S: HasCorpus<C, I>,
+{
+    fs::create_dir_all(dir)?;
+
+    let mut written = 0;
+    for idx in 0..state.corpus().count() {
+        let mut testcase = state.corpus().get(idx)?.borrow_mut();
+        let input = testcase.load_input()?;
+
+        let mut hasher = DefaultHasher::new();
+        hasher.write(input.bytes());
+
+        let path = dir.join(format!("id_{:016x}", hasher.finish()));
+        if path.exists() {
+            continue;
+        }
+
+        input.to_file(&path)?;
+        written += 1;
+    }
+
+    debug!("[*] saved {} new testcases to {:?}", written, dir);
+    Ok(written)
+}
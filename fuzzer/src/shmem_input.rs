@@ -0,0 +1,40 @@
+use libafl::{
+    bolts::shmem::{ShMem, ShMemProvider, StdShMemProvider},
+    Error,
+};
+
+use log::debug;
+
+/// env variable AFL++ targets read the id of the testcase shared memory from
+pub const SHM_FUZZ_ENV_VAR: &str = "__AFL_SHM_FUZZ_ID";
+
+/// env variable the persistent hook reads the largest testcase it gets from, QEMU inherits it
+pub const MAX_LEN_ENV_VAR: &str = "AFL_PERSISTENT_MAX_LEN";
+
+const LEN_SIZE: usize = std::mem::size_of::<u32>();
+
+/// Testcase delivery through shared memory, the way afl-fuzz does it.
+/// The region starts with the testcase length as a native u32 followed by the testcase bytes
+pub struct ShMemInput {
+    shmem: <StdShMemProvider as ShMemProvider>::Mem,
+    max_len: usize,
+}
+
+impl ShMemInput {
+    /// allocate the shared memory and export it to our environment so QEMU inherits it
+    pub fn new(max_len: usize) -> Result<Self, Error> {
+        let shmem = StdShMemProvider::new()?.new_map(max_len + LEN_SIZE)?;
+        shmem.write_to_env(SHM_FUZZ_ENV_VAR)?;
+
+        debug!("[*] testcase shared memory of {} bytes", shmem.len());
+        Ok(Self { shmem, max_len })
+    }
+
+    pub fn write_buf(&mut self, buf: &[u8]) {
+        let len = std::cmp::min(buf.len(), self.max_len);
+        let map = self.shmem.map_mut();
+
+        map[..LEN_SIZE].copy_from_slice(&(len as u32).to_ne_bytes());
+        map[LEN_SIZE..LEN_SIZE + len].copy_from_slice(&buf[..len]);
+    }
+}
@@ -0,0 +1,40 @@
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use libafl::Error;
+
+/// the signal that asked us to stop, 0 if none did. set from the signal handler, checked
+/// before every execution through `RunLimits`
+static STOP_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn handle_stop(sig: libc::c_int) {
+    // only async signal safe work in here, everything else happens in the fuzz loop
+    STOP_SIGNAL.store(sig, Ordering::SeqCst);
+}
+
+/// catch SIGINT and SIGTERM so the fuzzer can stop between executions and clean up after
+/// itself. the handler resets itself, a second signal kills the fuzzer right away in case it
+/// is stuck
+pub fn install_stop_handlers() -> Result<(), Error> {
+    for sig in [libc::SIGINT, libc::SIGTERM].iter() {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle_stop as usize;
+            action.sa_flags = libc::SA_RESETHAND;
+            libc::sigemptyset(&mut action.sa_mask);
+
+            if libc::sigaction(*sig, &action, std::ptr::null_mut()) != 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// the signal we were asked to stop with, if any
+pub fn stop_signal() -> Option<i32> {
+    match STOP_SIGNAL.load(Ordering::SeqCst) {
+        0 => None,
+        sig => Some(sig),
+    }
+}
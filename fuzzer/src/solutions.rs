@@ -0,0 +1,239 @@
+use core::cell::RefCell;
+use libafl::{
+    bolts::current_time,
+    corpus::{Corpus, OnDiskCorpus, Testcase},
+    inputs::Input,
+    state::HasMetadata,
+    Error,
+};
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    executor::status::{signal_name, ChildStatus},
+    feedback::{
+        context::CrashContextMetadata, crash::ExitStatusMetadata, reproduce::ReproductionMetadata,
+        stack_hash::StackHashMetadata,
+    },
+};
+
+/// Everything we know about a saved crash, written next to it as `<crash file>.json`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CrashReport {
+    pub signal: Option<i32>,
+    pub signal_name: Option<String>,
+    /// None if QEMU died with the target and the status is unknown
+    pub exit_status: Option<ChildStatus>,
+    /// start of the last block the target executed before it crashed
+    pub faulting_block: Option<u64>,
+    pub stack_hash: Option<u64>,
+    pub exec_time_us: Option<u64>,
+    /// queue entry the crash was mutated from
+    pub parent: Option<usize>,
+    pub stage: Option<String>,
+    /// milliseconds since the fuzzer started
+    pub time_ms: u64,
+    /// how many re-runs crashed, out of how many
+    pub reproduced: Option<(usize, usize)>,
+    /// command line that runs the crash under QEMU
+    pub command: String,
+}
+
+/// `OnDiskCorpus` that also writes a `CrashReport` sidecar for every crash
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(bound = "I: serde::de::DeserializeOwned")]
+pub struct CrashCorpus<I>
+where
+    I: Input,
+{
+    inner: OnDiskCorpus<I>,
+    dir_path: PathBuf,
+    /// number of the first crash we name, one past the highest `id_N` already in `dir_path` so
+    /// the crashes of an older run are not overwritten
+    first_id: usize,
+    /// QEMU command line of the target, `@@` is replaced with the crash file
+    command: Vec<String>,
+    /// the target reads the testcase from stdin
+    use_stdin: bool,
+    start_time: Duration,
+}
+
+impl<I> CrashCorpus<I>
+where
+    I: Input,
+{
+    pub fn new(dir_path: PathBuf, command: Vec<String>, use_stdin: bool) -> Result<Self, Error> {
+        let first_id = next_free_id(&dir_path);
+        if first_id > 0 {
+            debug!(
+                "[*] crash directory has older crashes, numbering from {}",
+                first_id
+            );
+        }
+
+        Ok(Self {
+            inner: OnDiskCorpus::new(dir_path.clone())?,
+            dir_path,
+            first_id,
+            command,
+            use_stdin,
+            start_time: current_time(),
+        })
+    }
+
+    fn report(&self, testcase: &Testcase<I>, path: &str) -> CrashReport {
+        let status = testcase
+            .metadata()
+            .get::<ExitStatusMetadata>()
+            .and_then(|m| m.status);
+        let signal = status.and_then(|s| s.signal());
+        let stack = testcase.metadata().get::<StackHashMetadata>();
+        let context = testcase.metadata().get::<CrashContextMetadata>();
+
+        let mut command: Vec<String> = self
+            .command
+            .iter()
+            .map(|arg| if arg == "@@" { path.to_string() } else { arg.clone() })
+            .collect();
+        if self.use_stdin {
+            command.push(format!("< {}", path));
+        }
+
+        CrashReport {
+            signal,
+            signal_name: signal.map(|s| signal_name(s).to_string()),
+            exit_status: status,
+            faulting_block: stack.and_then(|s| s.pcs.first().cloned()),
+            stack_hash: stack.map(|s| s.hash),
+            exec_time_us: context
+                .and_then(|c| c.exec_time)
+                .map(|t| t.as_micros() as u64),
+            parent: context.and_then(|c| c.parent),
+            stage: context.map(|c| c.stage.clone()),
+            time_ms: (current_time() - self.start_time).as_millis() as u64,
+            reproduced: testcase
+                .metadata()
+                .get::<ReproductionMetadata>()
+                .map(|m| (m.crashes, m.runs)),
+            command: command.join(" "),
+        }
+    }
+
+    fn write_report(&self, idx: usize) -> Result<(), Error> {
+        let testcase = self.inner.get(idx)?.borrow();
+        let path = match testcase.filename() {
+            Some(path) => path.clone(),
+            None => return Ok(()),
+        };
+
+        let report = self.report(&testcase, &path);
+        let data = serde_json::to_vec_pretty(&report)
+            .map_err(|e| Error::Serialize(format!("Error serializing crash report: {}", e)))?;
+
+        let report_path = format!("{}.json", path);
+        fs::write(&report_path, data)?;
+        debug!("[*] wrote crash report {}", report_path);
+        Ok(())
+    }
+}
+
+/// one past the highest `id_N` crash name in `dir`, 0 if there are none
+fn next_free_id(dir: &Path) -> usize {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            name.strip_prefix("id_")?.parse::<usize>().ok()
+        })
+        .map(|id| id + 1)
+        .max()
+        .unwrap_or(0)
+}
+
+impl<I> Corpus<I> for CrashCorpus<I>
+where
+    I: Input,
+{
+    #[inline]
+    fn count(&self) -> usize {
+        self.inner.count()
+    }
+
+    fn add(&mut self, mut testcase: Testcase<I>) -> Result<usize, Error> {
+        // `OnDiskCorpus` would name it after its index, starting at id_0 on every run
+        if testcase.filename().is_none() {
+            let name = format!("id_{}", self.first_id + self.inner.count());
+            testcase.set_filename(self.dir_path.join(name).to_string_lossy().to_string());
+        }
+
+        let idx = self.inner.add(testcase)?;
+        self.write_report(idx)?;
+        Ok(idx)
+    }
+
+    #[inline]
+    fn replace(&mut self, idx: usize, testcase: Testcase<I>) -> Result<(), Error> {
+        self.inner.replace(idx, testcase)
+    }
+
+    #[inline]
+    fn remove(&mut self, idx: usize) -> Result<Option<Testcase<I>>, Error> {
+        self.inner.remove(idx)
+    }
+
+    #[inline]
+    fn get(&self, idx: usize) -> Result<&RefCell<Testcase<I>>, Error> {
+        self.inner.get(idx)
+    }
+
+    #[inline]
+    fn current(&self) -> &Option<usize> {
+        self.inner.current()
+    }
+
+    #[inline]
+    fn current_mut(&mut self) -> &mut Option<usize> {
+        self.inner.current_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn numbers_after_the_crashes_of_an_older_run() {
+        let dir = env::temp_dir().join(format!("crash-test-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(next_free_id(&dir), 0);
+
+        // reports and triage names don't count
+        let names = [
+            "id_0",
+            "id_0.json",
+            "id_4",
+            "id_4.json",
+            "id:000007,stack:00",
+        ];
+        for name in names.iter() {
+            fs::write(dir.join(name), b"x").unwrap();
+        }
+
+        let first_id = next_free_id(&dir);
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(first_id, 5);
+    }
+}
@@ -2,7 +2,7 @@ use libafl::{
     bolts::current_time,
     stats::{ClientStats, MultiStats, Stats, UserStats},
 };
-use log::debug;
+use log::{debug, warn};
 use std::{io::Write, time::Duration};
 use std::{
     fs::{File, OpenOptions},
@@ -48,7 +48,8 @@ impl PlotMultiStats
         }
     }
 
-    fn write_global_plot(&mut self) -> Result<(), std::io::Error> {
+    /// append a line to the plot file. lines are written at most once a second unless `force`
+    fn write_global_plot(&mut self, force: bool) -> Result<(), std::io::Error> {
         if self.plot_file.is_none() {
             return Ok(());
         }
@@ -56,7 +57,7 @@ impl PlotMultiStats
         self.write_header_if_needed()?;
 
         let cur_time = current_time();
-        if (cur_time - self.last_write).as_secs() < 1 {
+        if !force && (cur_time - self.last_write).as_secs() < 1 {
             return Ok(());
         }
 
@@ -147,6 +148,19 @@ impl PlotMultiStats
 
 }
 
+impl Drop for PlotMultiStats {
+    /// the last second of fuzzing would be lost to the throttling, write it before we go
+    fn drop(&mut self) {
+        if let Err(e) = self.write_global_plot(true) {
+            warn!("Error while writing final plot data: {}", e);
+        }
+
+        if let Some(f) = &self.plot_file {
+            let _ = f.sync_all();
+        }
+    }
+}
+
 trait UserStatsInto {
     fn to_num(&self) -> Option<u64>;
 }
@@ -179,7 +193,7 @@ impl Stats for PlotMultiStats
     }
 
     fn display(&mut self, event_msg: String, sender_id: u32) {
-        self.write_global_plot()
+        self.write_global_plot(false)
             .expect("Error while writing plot data");
         self.stats.display(event_msg, sender_id)
     }
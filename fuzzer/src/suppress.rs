@@ -0,0 +1,192 @@
+use std::{collections::HashSet, fs, path::Path};
+
+use libafl::Error;
+use log::debug;
+
+use crate::{elf, executor::triage::Backtrace};
+
+/// Signatures of known crashes we don't want to hear about again.
+///
+/// The suppression file has one signature per line, `#` starts a comment:
+///
+/// ```text
+/// # start of the faulting block, as an offset into the target binary
+/// pc:0x1a2b4
+/// # the same as symbol+offset, resolved through the target's dynamic symbols
+/// sym:parse_header+0x40
+/// # stack hash, as printed in crash file names
+/// stack:5f1c03b8a2e4d917
+/// ```
+#[derive(Debug, Default)]
+pub struct Suppressions {
+    pcs: HashSet<u64>,
+    stacks: HashSet<u64>,
+}
+
+impl Suppressions {
+    /// read a suppression file. `load_base` is where QEMU maps the target (see
+    /// `elf::load_base`), it is added to the offsets so they compare with guest pcs
+    pub fn load(path: &Path, target: &str, load_base: u64) -> Result<Self, Error> {
+        let text = fs::read_to_string(path)?;
+        let mut suppressions = Self::default();
+
+        for (i, line) in text.lines().enumerate() {
+            let signature = parse_line(line, target, load_base).map_err(|reason| {
+                Error::IllegalArgument(format!(
+                    "{:?} line {}: {} ({})",
+                    path,
+                    i + 1,
+                    reason,
+                    line.trim()
+                ))
+            })?;
+
+            match signature {
+                Some(Signature::Pc(pc)) => {
+                    suppressions.pcs.insert(pc);
+                }
+                Some(Signature::Stack(hash)) => {
+                    suppressions.stacks.insert(hash);
+                }
+                None => {}
+            }
+        }
+
+        debug!("[*] loaded suppressions {:x?}", suppressions);
+        Ok(suppressions)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pcs.len() + self.stacks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// QEMU only logs blocks, so pc signatures are compared with the start of the faulting block
+    pub fn matches(&self, backtrace: &Backtrace) -> bool {
+        if self.stacks.contains(&backtrace.hash()) {
+            return true;
+        }
+
+        backtrace
+            .faulting_pc()
+            .map_or(false, |pc| self.pcs.contains(&pc))
+    }
+}
+
+/// one line of the suppression file
+#[derive(Debug, PartialEq)]
+enum Signature {
+    /// guest pc of the faulting block
+    Pc(u64),
+    Stack(u64),
+}
+
+/// None for blank lines and comments, the reason on a bad line
+fn parse_line(line: &str, target: &str, load_base: u64) -> Result<Option<Signature>, &'static str> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    if let Some(pc) = line.strip_prefix("pc:") {
+        let offset = parse_hex(pc).ok_or("invalid pc offset")?;
+        return Ok(Some(Signature::Pc(load_base + offset)));
+    }
+
+    if let Some(sym) = line.strip_prefix("sym:") {
+        let (name, offset) = match sym.find('+') {
+            Some(idx) => (
+                &sym[..idx],
+                parse_hex(&sym[idx + 1..]).ok_or("invalid offset")?,
+            ),
+            None => (sym, 0),
+        };
+
+        let addr = elf::find_addr_by_sym(target, name).map_err(|_| "symbol not found in target")?;
+        return Ok(Some(Signature::Pc(load_base + addr + offset)));
+    }
+
+    if let Some(stack) = line.strip_prefix("stack:") {
+        let hash = parse_hex(stack).ok_or("invalid stack hash")?;
+        return Ok(Some(Signature::Stack(hash)));
+    }
+
+    Err("expected pc:, sym: or stack:")
+}
+
+pub(crate) fn parse_hex(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    u64::from_str_radix(s, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: u64 = 0x5500000000;
+
+    #[test]
+    fn skips_blank_lines_and_comments() {
+        assert_eq!(parse_line("", "target", BASE), Ok(None));
+        assert_eq!(parse_line("  # pc:0x10", "target", BASE), Ok(None));
+    }
+
+    #[test]
+    fn parses_signatures() {
+        assert_eq!(
+            parse_line("pc:0x1a2b4", "target", BASE),
+            Ok(Some(Signature::Pc(BASE + 0x1a2b4)))
+        );
+        assert_eq!(
+            parse_line(" stack:5f1c03b8a2e4d917 ", "target", BASE),
+            Ok(Some(Signature::Stack(0x5f1c03b8a2e4d917)))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_lines() {
+        assert_eq!(
+            parse_line("pc:xyz", "target", BASE),
+            Err("invalid pc offset")
+        );
+        assert_eq!(
+            parse_line("stack:", "target", BASE),
+            Err("invalid stack hash")
+        );
+        assert_eq!(
+            parse_line("sym:main+zz", "target", BASE),
+            Err("invalid offset")
+        );
+        assert_eq!(
+            parse_line("sym:main", "/nonexistent", BASE),
+            Err("symbol not found in target")
+        );
+        assert_eq!(
+            parse_line("0x1a2b4", "target", BASE),
+            Err("expected pc:, sym: or stack:")
+        );
+    }
+
+    #[test]
+    fn matches_faulting_pc_and_stack_hash() {
+        let backtrace = Backtrace::new(&[BASE + 0x1a2b4, BASE + 0x1000], 5, BASE);
+
+        let mut suppressions = Suppressions::default();
+        assert!(!suppressions.matches(&backtrace));
+
+        // a caller is not the faulting block
+        suppressions.pcs.insert(BASE + 0x1000);
+        assert!(!suppressions.matches(&backtrace));
+
+        suppressions.stacks.insert(backtrace.hash());
+        assert!(suppressions.matches(&backtrace));
+
+        let mut suppressions = Suppressions::default();
+        suppressions.pcs.insert(BASE + 0x1a2b4);
+        assert!(suppressions.matches(&backtrace));
+    }
+}
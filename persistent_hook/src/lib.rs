@@ -1,12 +1,60 @@
+use std::env;
 use std::os::raw::c_int;
+use std::sync::atomic::{AtomicU32, Ordering};
 
+/// the fuzzer passes its `max_input_len` to QEMU in this variable
+const MAX_INPUT_LEN_ENV_VAR: &str = "AFL_PERSISTENT_MAX_LEN";
+
+/// largest testcase we copy into the guest, no limit unless the fuzzer tells us its
+/// `max_input_len`
+static MAX_INPUT_LEN: AtomicU32 = AtomicU32::new(u32::MAX);
+
+/// prefix of `struct arm64_regs` from qemuafl/qemuafl/api.h, QEMU hands us a pointer to it
+#[repr(C)]
+pub struct Arm64Regs {
+    pub x: [u64; 29],
+    pub fp: u64,
+    pub lr: u64,
+    pub sp: u64,
+    pub pc: u64,
+}
+
+/// returning 1 tells QEMU we want testcases delivered through shared memory
 #[no_mangle]
-pub extern fn afl_persistent_hook_init() -> c_int {
+pub extern "C" fn afl_persistent_hook_init() -> c_int {
     println!("afl_persistent_hook init");
-    return 1;
+
+    if let Some(max_len) = env::var(MAX_INPUT_LEN_ENV_VAR)
+        .ok()
+        .and_then(|len| len.parse().ok())
+    {
+        MAX_INPUT_LEN.store(max_len, Ordering::Relaxed);
+    }
+
+    1
 }
 
+/// called by QEMU before every persistent iteration with the testcase from shared memory.
+/// the target function is expected to get a buffer in x0 and its length in x1
+///
+/// # Safety
+///
+/// QEMU calls this with valid guest registers and an input buffer of `input_buf_len` bytes,
+/// and x0 must point to a guest buffer large enough for the testcase
 #[no_mangle]
-pub extern fn afl_persistent_hook() {
-    println!("afl_persistent_hook!");
+pub unsafe extern "C" fn afl_persistent_hook(
+    regs: *mut Arm64Regs,
+    guest_base: u64,
+    input_buf: *const u8,
+    input_buf_len: u32,
+) {
+    let len = std::cmp::min(input_buf_len, MAX_INPUT_LEN.load(Ordering::Relaxed));
+
+    // SAFETY: the pointers come from QEMU, see the safety section above
+    unsafe {
+        let regs = &mut *regs;
+        let dst = (regs.x[0] + guest_base) as *mut u8;
+        std::ptr::copy_nonoverlapping(input_buf, dst, len as usize);
+        regs.x[1] = len as u64;
+    }
 }